@@ -0,0 +1,273 @@
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_derive::Serialize;
+use std::convert::Infallible;
+use std::error::Error;
+use std::net::SocketAddr;
+
+use crate::config::ControlSettings;
+use crate::control::{Control, ControlError};
+
+// the control API, JSON over HTTP on localhost:
+//
+//   GET  /status                     every group of the run
+//   GET  /groups/<name>              one group
+//   POST /groups/<name>/pause        no new pass after the current one
+//   POST /groups/<name>/resume
+//   POST /groups/<name>/run          one pass of a paused group
+//   POST /groups/<name>/stop         cancel the current pass, no new ones
+//   POST /reload                     read the config file again
+//   POST /stop                       stop every group and exit
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+// bind now so a taken port stops the bot before any browser starts, then
+// serve in the background for the rest of the run
+pub fn serve(settings: &ControlSettings, control: Control) -> Result<(), Box<dyn Error>> {
+    let address: SocketAddr = settings.address.parse()
+        .map_err(|err| format!("invalid control address {}: {}", settings.address, err))?;
+    let token = settings.token.clone();
+
+    let service = make_service_fn(move |_| {
+        let control = control.clone();
+        let token = token.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let control = control.clone();
+                let token = token.clone();
+                async move { Ok::<_, Infallible>(handle(request, &control, &token).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&address)
+        .map_err(|err| format!("cannot listen on {}: {}", address, err))?
+        .serve(service);
+
+    tracing::info!(address = %address, "control API listening");
+    tokio::spawn(async move {
+        if let Err(err) = server.await {
+            tracing::error!(error = %err, "control API stopped");
+        }
+    });
+    Ok(())
+}
+
+async fn handle(request: Request<Body>, control: &Control, token: &str) -> Response<Body> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    tracing::debug!(method = %method, path = %path, "control request");
+
+    if !token.is_empty() {
+        let expected = format!("Bearer {}", token);
+        let authorized = request.headers().get(hyper::header::AUTHORIZATION)
+            .is_some_and(|value| value.as_bytes() == expected.as_bytes());
+        if !authorized {
+            return error(StatusCode::UNAUTHORIZED, "missing or wrong Authorization: Bearer token".to_string());
+        }
+    }
+
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+    let result = match (&method, segments.as_slice()) {
+        (&Method::GET, ["status"]) => Ok(json(&control.status())),
+        (&Method::GET, ["groups", name]) => control.group(name).map(|status| json(&status)),
+        (&Method::POST, ["groups", name, command]) => {
+            let result = match *command {
+                "pause" => control.pause(name),
+                "resume" => control.resume(name),
+                "run" => control.run(name),
+                "stop" => control.stop(name),
+                _ => return error(StatusCode::NOT_FOUND, format!("unknown command {}", command)),
+            };
+            if result.is_ok() {
+                tracing::info!(group = %name, command = %command, "control command");
+            }
+            result.map(|status| json(&status))
+        },
+        // reading and checking the config file blocks, keep it off the runtime's threads
+        (&Method::POST, ["reload"]) => {
+            let control = control.clone();
+            match tokio::task::spawn_blocking(move || control.reload()).await {
+                Ok(result) => result.map(|report| json(&report)),
+                Err(err) => return error(StatusCode::INTERNAL_SERVER_ERROR, format!("reload failed: {}", err)),
+            }
+        },
+        (&Method::POST, ["stop"]) => {
+            tracing::info!("stop requested through the control API");
+            control.stop_all();
+            Ok(json(&control.status()))
+        },
+        (_, ["status"]) | (_, ["groups", _]) | (_, ["groups", _, _]) | (_, ["reload"]) | (_, ["stop"]) => {
+            return error(StatusCode::METHOD_NOT_ALLOWED, format!("{} is not allowed on {}", method, path));
+        },
+        _ => return error(StatusCode::NOT_FOUND, format!("no endpoint {}", path)),
+    };
+
+    match result {
+        Ok(body) => response(StatusCode::OK, body),
+        Err(err) => {
+            let status = match err {
+                ControlError::UnknownGroup(_) => StatusCode::NOT_FOUND,
+                ControlError::Conflict(_) => StatusCode::CONFLICT,
+                ControlError::InvalidConfig(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+            error(status, err.to_string())
+        },
+    }
+}
+
+fn json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
+
+fn error(status: StatusCode, message: String) -> Response<Body> {
+    response(status, json(&ErrorBody { error: message }))
+}
+
+fn response(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::CancelToken;
+    use crate::notify::Notifiers;
+    use crate::validate;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    const CONFIG: &str = r#"
+        profile = ""
+        screenshot_path = ""
+        timeout = 1000
+        groups = [
+            { name = "amazon", steps = [{ action = "Refresh" }] },
+            { name = "newegg", steps = [{ action = "Refresh" }] },
+        ]
+    "#;
+
+    // a config file of its own per test, reload reads it again
+    fn config_file(test: &str, source: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("scraper-api-{}-{}.toml", test, std::process::id()));
+        fs::write(&path, source).unwrap();
+        path
+    }
+
+    fn control(path: &Path) -> Control {
+        let path = path.to_str().unwrap();
+        let (config, _) = validate::check_config(path, &[]).unwrap();
+        let names = vec!["amazon".to_string(), "newegg".to_string()];
+        Control::new(path, Arc::new(config), Notifiers::dry_run(), CancelToken::new(), &names)
+    }
+
+    async fn call(control: &Control, token: &str, method: Method, uri: &str, authorization: Option<&str>) -> (StatusCode, serde_json::Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(authorization) = authorization {
+            request = request.header(hyper::header::AUTHORIZATION, authorization);
+        }
+        let response = handle(request.body(Body::empty()).unwrap(), control, token).await;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn routes_the_group_commands() {
+        let path = config_file("routes", CONFIG);
+        let control = control(&path);
+
+        let (status, body) = call(&control, "", Method::GET, "/status", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["groups"].as_array().unwrap().len(), 2);
+
+        let (status, body) = call(&control, "", Method::POST, "/groups/amazon/pause", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["name"], "amazon");
+        let (status, body) = call(&control, "", Method::GET, "/groups/amazon/", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["state"], "paused");
+
+        let (status, _) = call(&control, "", Method::POST, "/groups/amazon/run", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&control, "", Method::POST, "/groups/amazon/resume", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&control, "", Method::POST, "/groups/newegg/stop", None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = call(&control, "", Method::POST, "/stop", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(control.stop_requested());
+        fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn answers_with_the_matching_status_code() {
+        let path = config_file("codes", CONFIG);
+        let control = control(&path);
+
+        let (status, body) = call(&control, "", Method::GET, "/groups/bestbuy", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "no running group named [bestbuy]");
+        let (status, _) = call(&control, "", Method::POST, "/groups/amazon/jump", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = call(&control, "", Method::GET, "/nothing", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = call(&control, "", Method::DELETE, "/status", None).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        let (status, _) = call(&control, "", Method::GET, "/groups/amazon/pause", None).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+
+        // run only applies to a paused group
+        let (status, body) = call(&control, "", Method::POST, "/groups/amazon/run", None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"], "[amazon] is not paused, it runs already");
+        fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn token_is_required_when_set() {
+        let path = config_file("auth", CONFIG);
+        let control = control(&path);
+
+        let (status, _) = call(&control, "secret", Method::GET, "/status", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = call(&control, "secret", Method::GET, "/status", Some("Bearer wrong")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = call(&control, "secret", Method::GET, "/status", Some("secret")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        // checked before routing, unknown paths do not tell anything either
+        let (status, _) = call(&control, "secret", Method::GET, "/nothing", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = call(&control, "secret", Method::GET, "/status", Some("Bearer secret")).await;
+        assert_eq!(status, StatusCode::OK);
+        fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn reload_reports_new_groups_as_requiring_a_restart() {
+        let path = config_file("reload", CONFIG);
+        let control = control(&path);
+
+        fs::write(&path, CONFIG.replace("newegg", "bestbuy")).unwrap();
+        let (status, body) = call(&control, "", Method::POST, "/reload", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["requires_restart"], serde_json::json!(["bestbuy"]));
+        assert_eq!(body["removed"], serde_json::json!(["newegg"]));
+        // the run still only knows the groups it started with
+        let (status, _) = call(&control, "", Method::GET, "/groups/bestbuy", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        fs::write(&path, CONFIG.replace("action = \"Refresh\"", "action = { Wait = 1 }, when = \"a &&\"")).unwrap();
+        let (status, body) = call(&control, "", Method::POST, "/reload", None).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["error"].as_str().unwrap().starts_with("config not reloaded: "), "{}", body);
+        fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,276 @@
+use serde_derive::Serialize;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::backend::BrowserBackend;
+use crate::browser::{file_name, BrowserOutcome};
+use crate::config::{ArtifactSettings, Config, Step, StepAction};
+use crate::journal::{self, Retry};
+
+// saves what is needed to debug a failed step: a directory per failure with
+// failure.json (error, url, frames, windows, console), step.txt (the step as
+// written in the config), page.html and screenshot.png
+#[derive(Clone)]
+pub struct Artifacts {
+    settings: ArtifactSettings,
+    path: PathBuf,
+}
+
+// what the group knows about the failed step
+pub struct Failure<'a> {
+    pub group: &'a str,
+    pub iteration: u64,
+    pub step: &'a Step,
+    pub error: &'a BrowserOutcome,
+    pub attempts: u32,
+    pub retries: &'a [Retry],
+    pub url: &'a str,
+    pub frames: &'a [String],
+}
+
+// failure.json
+#[derive(Serialize)]
+struct Report<'a> {
+    group: &'a str,
+    iteration: u64,
+    step: &'a str,
+    action: &'static str,
+    time: u64,
+    kind: &'static str,
+    error: String,
+    attempts: u32,
+    retries: &'a [Retry],
+    url: &'a str,
+    frames: &'a [String],
+    windows: Vec<String>,
+    // None when the page's console was not captured
+    console: Option<Vec<String>>,
+    files: Vec<String>,
+    // what could not be collected, and why
+    missing: Vec<String>,
+}
+
+impl Artifacts {
+    pub fn new(config: &Config) -> Artifacts {
+        let path = if config.artifacts.path.is_empty() {
+            Path::new(&config.screenshot_path).join("failures")
+        } else {
+            PathBuf::from(&config.artifacts.path)
+        };
+        Artifacts { settings: config.artifacts.clone(), path }
+    }
+
+    pub fn disabled() -> Artifacts {
+        Artifacts {
+            settings: ArtifactSettings {
+                enabled: false,
+                path: String::new(),
+                screenshot: false,
+                page_source: false,
+                console: false,
+            },
+            path: PathBuf::new(),
+        }
+    }
+
+    // write the bundle and return its directory, None when disabled. Anything
+    // the browser cannot give (a dialog is open, the session is gone) is
+    // listed under missing in failure.json instead of failing the bundle
+    pub async fn save(&self, failure: &Failure<'_>, browser: &mut dyn BrowserBackend) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        if !self.settings.enabled {
+            return Ok(None);
+        }
+
+        let time = journal::now();
+        let dir = self.path.join(format!("{}-{}-{}", time, file_name(failure.group), file_name(&failure.step.name)));
+        std::fs::create_dir_all(&dir).map_err(|err| format!("cannot create {}: {}", dir.display(), err))?;
+        let mut files = Vec::new();
+        let mut missing = Vec::new();
+
+        if self.settings.screenshot {
+            match browser.screenshot(false, None, "failure").await {
+                Ok(path) => match move_file(Path::new(&path), &dir.join("screenshot.png")) {
+                    Ok(_) => files.push("screenshot.png".to_string()),
+                    Err(err) => missing.push(format!("screenshot: cannot move {}: {}", path, err)),
+                },
+                Err(err) => missing.push(format!("screenshot: {}", err)),
+            }
+        }
+
+        if self.settings.page_source {
+            match browser.page_source().await {
+                Ok(source) => write(&dir, "page.html", &source, &mut files, &mut missing),
+                Err(err) => missing.push(format!("page source: {}", err)),
+            }
+        }
+
+        let console = if self.settings.console {
+            match browser.console_log().await {
+                Ok(Some(entries)) => Some(entries),
+                Ok(None) => {
+                    missing.push("console: not captured on this page".to_string());
+                    None
+                },
+                Err(err) => {
+                    missing.push(format!("console: {}", err));
+                    None
+                },
+            }
+        } else {
+            None
+        };
+
+        let windows = match browser.window_handles().await {
+            Ok(windows) => windows,
+            Err(err) => {
+                missing.push(format!("windows: {}", err));
+                Vec::new()
+            },
+        };
+
+        write(&dir, "step.txt", &step_text(failure.step), &mut files, &mut missing);
+
+        let report = Report {
+            group: failure.group,
+            iteration: failure.iteration,
+            step: &failure.step.name,
+            action: failure.step.action.kind(),
+            time,
+            kind: failure.error.kind(),
+            error: failure.error.to_string(),
+            attempts: failure.attempts,
+            retries: failure.retries,
+            url: failure.url,
+            frames: failure.frames,
+            windows,
+            console,
+            files,
+            missing,
+        };
+        let report = serde_json::to_string_pretty(&report)?;
+        std::fs::write(dir.join("failure.json"), report)
+            .map_err(|err| format!("cannot write {}: {}", dir.join("failure.json").display(), err))?;
+        Ok(Some(dir))
+    }
+}
+
+fn write(dir: &Path, name: &str, contents: &str, files: &mut Vec<String>, missing: &mut Vec<String>) {
+    match std::fs::write(dir.join(name), contents) {
+        Ok(_) => files.push(name.to_string()),
+        Err(err) => missing.push(format!("{}: {}", name, err)),
+    }
+}
+
+// screenshots are saved in screenshot_path first, which may be on another drive
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to)?;
+    std::fs::remove_file(from)
+}
+
+// the fields of the step the config sets, without what resolve adds. A Script
+// shows its source or file, not the code read from it
+fn step_text(step: &Step) -> String {
+    let action = match &step.action {
+        StepAction::Script{source, file, args, into, is_async, ..} => {
+            format!("Script {{ source: {:?}, file: {:?}, args: {:?}, into: {:?}, async: {} }}", source, file, args, into, is_async)
+        },
+        action => format!("{:?}", action),
+    };
+    let mut text = format!("name = {:?}\naction = {}\n", step.name, action);
+    for (key, value) in &[("when", &step.when), ("if_cond", &step.if_cond), ("if_not_cond", &step.if_not_cond)] {
+        if !value.is_empty() {
+            text.push_str(&format!("{} = {:?}\n", key, value));
+        }
+    }
+    text.push_str(&format!("optional = {}\nwait_max = {}\ndelay = {}\n", step.optional, step.wait_max, step.delay));
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::browser::no_such_element;
+    use crate::mock::{MockBrowser, MockElement, MockPage};
+    use std::fs;
+
+    #[tokio::test]
+    async fn bundle_has_the_four_files() {
+        let root = std::env::temp_dir().join(format!("scraper-artifacts-{}", std::process::id()));
+        fs::remove_dir_all(&root).ok();
+        fs::create_dir_all(&root).unwrap();
+        let artifacts = Artifacts {
+            settings: ArtifactSettings { enabled: true, path: String::new(), screenshot: true, page_source: true, console: true },
+            path: root.join("failures"),
+        };
+
+        let mut browser = MockBrowser::new(1)
+            .page("https://shop.test/item", MockPage::new().title("Item").element("#price", MockElement::new()));
+        browser.goto("https://shop.test/item").await.unwrap();
+        browser.screenshot_path = root.to_string_lossy().into_owned();
+        // the mock only names the screenshot
+        fs::write(root.join("mock-1-failure.png"), b"png").unwrap();
+
+        let step: Step = toml::from_str(r##"
+            name = "buy now"
+            action = { Find = { selector = "#buy", action = "Click" } }
+            when = "price"
+            wait_max = 100
+        "##).unwrap();
+        let error = no_such_element("#buy".to_string());
+        let retries = vec![Retry { error: error.to_string(), count: 2 }];
+        let frames = Vec::new();
+        let failure = Failure {
+            group: "shop/1",
+            iteration: 3,
+            step: &step,
+            error: &error,
+            attempts: 3,
+            retries: &retries,
+            url: "https://shop.test/item",
+            frames: &frames,
+        };
+        let dir = artifacts.save(&failure, &mut browser).await.unwrap().unwrap();
+
+        assert!(dir.file_name().unwrap().to_str().unwrap().ends_with("-shop_1-buy_now"), "{}", dir.display());
+        let mut names: Vec<String> = fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["failure.json", "page.html", "screenshot.png", "step.txt"]);
+        assert!(!root.join("mock-1-failure.png").exists());
+        assert_eq!(fs::read(dir.join("screenshot.png")).unwrap(), b"png");
+        assert!(fs::read_to_string(dir.join("page.html")).unwrap().contains("<title>Item</title>"));
+
+        let step_text = fs::read_to_string(dir.join("step.txt")).unwrap();
+        assert!(step_text.starts_with("name = \"buy now\"\naction = Find { selector: "), "{}", step_text);
+        assert!(step_text.ends_with("when = \"price\"\noptional = false\nwait_max = 100\ndelay = 0\n"), "{}", step_text);
+        assert!(!step_text.contains("condition"), "{}", step_text);
+
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("failure.json")).unwrap()).unwrap();
+        assert_eq!(report["group"], "shop/1");
+        assert_eq!(report["iteration"], 3);
+        assert_eq!(report["action"], "Find");
+        assert_eq!(report["kind"], "NoSuchElement");
+        assert_eq!(report["retries"][0]["count"], 2);
+        assert_eq!(report["windows"], serde_json::json!(["mock-tab-0"]));
+        assert_eq!(report["console"], serde_json::Value::Null);
+        assert_eq!(report["files"], serde_json::json!(["screenshot.png", "page.html", "step.txt"]));
+        assert_eq!(report["missing"], serde_json::json!(["console: not captured on this page"]));
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn script_steps_leave_out_the_code() {
+        let mut step: Step = toml::from_str(r#"action = { Script = { file = "check.js", into = "ok" } }"#).unwrap();
+        if let StepAction::Script{code, ..} = &mut step.action {
+            *code = "return secret();".to_string();
+        }
+        let text = step_text(&step);
+        assert!(text.contains("action = Script { source: \"\", file: \"check.js\", args: [], into: \"ok\", async: false }"), "{}", text);
+        assert!(!text.contains("secret"), "{}", text);
+    }
+}
@@ -0,0 +1,143 @@
+use regex::Regex;
+
+use crate::browser::BrowserOutcome;
+use crate::config::{CountOp, MatchMode, UrlComponent};
+
+// compare page text with the value of a Match step. Surrounding whitespace
+// is ignored, element text usually carries some
+pub fn text_matches(mode: MatchMode, expected: &str, actual: &str) -> Result<bool, BrowserOutcome> {
+    let actual = actual.trim();
+    match mode {
+        MatchMode::Contains => Ok(actual.contains(expected)),
+        MatchMode::Exact => Ok(actual == expected.trim()),
+        MatchMode::Regex => Ok(compile(expected)?.is_match(actual)),
+        MatchMode::Glob => Ok(compile(&glob_regex(expected))?.is_match(actual)),
+    }
+}
+
+// an anchored regex matching the same text as the glob
+pub fn glob_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+// the component of url a MatchUrl step compares, empty when the url has none
+pub fn url_component(url: &str, component: UrlComponent) -> Result<String, BrowserOutcome> {
+    if component == UrlComponent::Full {
+        return Ok(url.to_string());
+    }
+    let parsed = match url::Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(err) => return Err(failed("MatchUrl", "a valid url".to_string(), format!("\"{}\" ({})", url, err))),
+    };
+    Ok(match component {
+        UrlComponent::Full => url.to_string(),
+        UrlComponent::Host => parsed.host_str().unwrap_or_default().to_string(),
+        UrlComponent::Path => parsed.path().to_string(),
+        UrlComponent::Query => parsed.query().unwrap_or_default().to_string(),
+    })
+}
+
+pub fn compile(pattern: &str) -> Result<Regex, BrowserOutcome> {
+    Regex::new(pattern).map_err(|err| BrowserOutcome::InvalidPattern(format!("{}, {}", pattern, err)))
+}
+
+// "contains \"Sold out\"", used for the expected side of a failure
+pub fn describe_match(mode: MatchMode, expected: &str) -> String {
+    match mode {
+        MatchMode::Contains => format!("contains \"{}\"", expected),
+        MatchMode::Exact => format!("\"{}\"", expected),
+        MatchMode::Regex => format!("matches /{}/", expected),
+        MatchMode::Glob => format!("matches {}", expected),
+    }
+}
+
+pub fn count_matches(op: CountOp, actual: usize, expected: usize) -> bool {
+    match op {
+        CountOp::Equal => actual == expected,
+        CountOp::NotEqual => actual != expected,
+        CountOp::Less => actual < expected,
+        CountOp::LessOrEqual => actual <= expected,
+        CountOp::Greater => actual > expected,
+        CountOp::GreaterOrEqual => actual >= expected,
+    }
+}
+
+pub fn describe_count(op: CountOp, expected: usize) -> String {
+    let op = match op {
+        CountOp::Equal => "==",
+        CountOp::NotEqual => "!=",
+        CountOp::Less => "<",
+        CountOp::LessOrEqual => "<=",
+        CountOp::Greater => ">",
+        CountOp::GreaterOrEqual => ">=",
+    };
+    format!("{} {}", op, expected)
+}
+
+pub fn failed(check: &str, expected: String, actual: String) -> BrowserOutcome {
+    BrowserOutcome::AssertionFailed{ check: check.to_string(), expected, actual }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://shop.test/gp/item/B01?ref=nav&qty=2";
+
+    #[test]
+    fn every_mode_matches_trimmed_text() {
+        let text = "  In stock: 3 left \n";
+        assert!(text_matches(MatchMode::Contains, "3 left", text).unwrap());
+        assert!(!text_matches(MatchMode::Contains, "sold out", text).unwrap());
+        assert!(text_matches(MatchMode::Exact, " In stock: 3 left ", text).unwrap());
+        assert!(!text_matches(MatchMode::Exact, "In stock", text).unwrap());
+        assert!(text_matches(MatchMode::Regex, r"^In stock: \d+ left$", text).unwrap());
+        assert!(!text_matches(MatchMode::Regex, r"^\d+ left$", text).unwrap());
+        assert!(text_matches(MatchMode::Glob, "In stock: ? left", text).unwrap());
+        assert!(!text_matches(MatchMode::Glob, "In stock", text).unwrap());
+    }
+
+    #[test]
+    fn glob_is_anchored_and_escapes_the_rest() {
+        assert_eq!(glob_regex("*.test/a?c"), r"^.*\.test/a.c$");
+        assert!(compile(&glob_regex("$4*.99")).unwrap().is_match("$499.99"));
+        assert!(!compile(&glob_regex("$4*.99")).unwrap().is_match("$4.990"));
+        assert!(!compile(&glob_regex("[a]")).unwrap().is_match("a"));
+    }
+
+    #[test]
+    fn invalid_regex_is_an_invalid_pattern() {
+        match text_matches(MatchMode::Regex, "(", "text") {
+            Err(BrowserOutcome::InvalidPattern(_)) => {},
+            other => panic!("expected InvalidPattern, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn url_components() {
+        assert_eq!(url_component(URL, UrlComponent::Full).unwrap(), URL);
+        assert_eq!(url_component(URL, UrlComponent::Host).unwrap(), "shop.test");
+        assert_eq!(url_component(URL, UrlComponent::Path).unwrap(), "/gp/item/B01");
+        assert_eq!(url_component(URL, UrlComponent::Query).unwrap(), "ref=nav&qty=2");
+        assert_eq!(url_component("https://shop.test/", UrlComponent::Query).unwrap(), "");
+    }
+
+    #[test]
+    fn only_the_full_url_may_be_unparsable() {
+        assert_eq!(url_component("about:blank", UrlComponent::Full).unwrap(), "about:blank");
+        assert_eq!(url_component("about:blank", UrlComponent::Host).unwrap(), "");
+        match url_component("not a url", UrlComponent::Path) {
+            Err(BrowserOutcome::AssertionFailed{ check, .. }) => assert_eq!(check, "MatchUrl"),
+            other => panic!("expected AssertionFailed, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use async_trait::async_trait;
+
+use crate::browser::BrowserOutcome;
+use crate::config::{DialogAction, Selector, SelectBy};
+
+// everything the step engine needs from a browser. Browser implements it
+// on top of fantoccini, MockBrowser implements it in memory so groups
+// and steps can be run without Firefox.
+// Elements are addressed by a Selector, see config.rs.
+#[async_trait]
+pub trait BrowserBackend: Send {
+    // navigate the current tab to a URL
+    async fn goto(&mut self, dest: &str) -> Result<(), BrowserOutcome>;
+
+    // succeed if an element matching the selector exists
+    async fn find(&mut self, selector: &Selector) -> Result<(), BrowserOutcome>;
+
+    async fn click(&mut self, selector: &Selector) -> Result<(), BrowserOutcome>;
+
+    // set the value of a form field
+    async fn insert(&mut self, selector: &Selector, value: &str) -> Result<(), BrowserOutcome>;
+
+    // empty a form field
+    async fn clear(&mut self, selector: &Selector) -> Result<(), BrowserOutcome>;
+
+    // type into the element, keys is already translated by keys.rs
+    async fn send_keys(&mut self, selector: &Selector, keys: &str) -> Result<(), BrowserOutcome>;
+
+    // choose an option of a SELECT element
+    async fn select(&mut self, selector: &Selector, option: &SelectBy) -> Result<(), BrowserOutcome>;
+
+    // click a checkbox or radio button only if it is not already in that state
+    async fn set_checked(&mut self, selector: &Selector, checked: bool) -> Result<(), BrowserOutcome>;
+
+    async fn hover(&mut self, selector: &Selector) -> Result<(), BrowserOutcome>;
+
+    async fn scroll_into_view(&mut self, selector: &Selector) -> Result<(), BrowserOutcome>;
+
+    async fn double_click(&mut self, selector: &Selector) -> Result<(), BrowserOutcome>;
+
+    async fn right_click(&mut self, selector: &Selector) -> Result<(), BrowserOutcome>;
+
+    // None when the element exists but does not have the attribute
+    async fn find_attribute(&mut self, selector: &Selector, attr: &str) -> Result<Option<String>, BrowserOutcome>;
+
+    // visible text of the element
+    async fn find_text(&mut self, selector: &Selector) -> Result<String, BrowserOutcome>;
+
+    async fn current_url(&mut self) -> Result<String, BrowserOutcome>;
+
+    // run a script in the current frame and return its result. An async
+    // script gets a callback as its last argument and passes it the result
+    async fn run_script(&mut self, source: &str, args: Vec<serde_json::Value>, is_async: bool) -> Result<serde_json::Value, BrowserOutcome>;
+
+    // text of the open alert, confirm or prompt, None when there is none
+    async fn dialog_text(&mut self) -> Result<Option<String>, BrowserOutcome>;
+
+    // type text into a prompt unless it is empty, then close the dialog
+    async fn answer_dialog(&mut self, action: DialogAction, text: &str) -> Result<(), BrowserOutcome>;
+
+    // HTML of the current frame as the browser sees it now
+    async fn page_source(&mut self) -> Result<String, BrowserOutcome>;
+
+    // handles of every open window/tab, in creation order
+    async fn window_handles(&mut self) -> Result<Vec<String>, BrowserOutcome>;
+
+    // console messages and uncaught errors of the current document since it
+    // was loaded by goto or refresh. None when they were not captured
+    async fn console_log(&mut self) -> Result<Option<Vec<String>>, BrowserOutcome>;
+
+    // document.readyState: "loading", "interactive" or "complete"
+    async fn ready_state(&mut self) -> Result<String, BrowserOutcome>;
+
+    // title of the current document
+    async fn title(&mut self) -> Result<String, BrowserOutcome>;
+
+    // the element exists and takes up space on the page
+    async fn is_displayed(&mut self, selector: &Selector) -> Result<bool, BrowserOutcome>;
+
+    // the element exists and is not disabled
+    async fn is_enabled(&mut self, selector: &Selector) -> Result<bool, BrowserOutcome>;
+
+    // number of elements matching the selector, 0 is not an error
+    async fn count(&mut self, selector: &Selector) -> Result<usize, BrowserOutcome>;
+
+    async fn refresh(&mut self) -> Result<(), BrowserOutcome>;
+
+    // enter the IFRAME matching the selector
+    async fn switch_frame(&mut self, selector: &Selector) -> Result<(), BrowserOutcome>;
+
+    // enter the Nth frame of the current document, counting from 0
+    async fn switch_frame_index(&mut self, index: usize) -> Result<(), BrowserOutcome>;
+
+    // leave the current IFRAME for the one around it
+    async fn parent_frame(&mut self) -> Result<(), BrowserOutcome>;
+
+    // leave every IFRAME
+    async fn top_window(&mut self) -> Result<(), BrowserOutcome>;
+
+    // the frames entered from the top window, innermost last. Navigating,
+    // refreshing and switching tabs empty it
+    fn frames(&self) -> Vec<String>;
+
+    // save a PNG of the viewport, the whole scrollable page, or the element
+    // matching the selector. name is added to the file name, the path is returned
+    async fn screenshot(&mut self, full_page: bool, selector: Option<&Selector>, name: &str) -> Result<String, BrowserOutcome>;
+
+    // each group runs in its own tab, indexed in creation order
+    async fn switch_tab(&mut self, index: usize) -> Result<(), BrowserOutcome>;
+}
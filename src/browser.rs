@@ -1,9 +1,48 @@
-use std::process::{Command, Stdio};
 use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
 use tokio::time::timeout;
+use async_trait::async_trait;
 use fantoccini::{Client, Locator, Element};
+use fantoccini::error::CmdError;
+use image::RgbaImage;
+use webdriver::error::{ErrorStatus, WebDriverError};
+
+use crate::backend::BrowserBackend;
+use crate::config::{BrowserKind, DialogAction, DriverSettings, Selector, SelectBy};
+use crate::locator::{self, Query, LOCATE_JS};
+use crate::process::DriverProcess;
+
+// stitched full page screenshots stop growing past this many pixels
+const MAX_FULL_PAGE_HEIGHT: u32 = 32768;
+
+// shared by every session so concurrent groups never write the same file
+static SCREENSHOT_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// the pointer of the W3C actions API, fantoccini 0.14 has no actions so they
+// are sent as raw commands. Page scripts see them as trusted input
+const MOUSE: &str = "mouse";
+
+// WebDriver has no console API, so after goto and refresh the page's console
+// and uncaught errors are copied into window.__scraperConsole. Messages logged
+// before the hook ran, or after the page navigated by itself, are not seen
+const CONSOLE_HOOK_JS: &str = "
+if (!window.__scraperConsole) {
+    const entries = window.__scraperConsole = [];
+    const add = (entry) => { entries.push(entry); if (entries.length > 500) entries.shift(); };
+    for (const level of ['log', 'info', 'warn', 'error', 'debug']) {
+        const original = console[level];
+        console[level] = function (...args) {
+            add(level + ': ' + args.map(String).join(' '));
+            return original.apply(this, args);
+        };
+    }
+    window.addEventListener('error', (event) => add('uncaught: ' + event.message + ' at ' + event.filename + ':' + event.lineno));
+    window.addEventListener('unhandledrejection', (event) => add('unhandled rejection: ' + String(event.reason)));
+}
+return true;";
 
 // crate-wide errors to wrap browser operation results and handle timeouts
 #[derive(Debug)]
@@ -12,13 +51,32 @@ pub enum BrowserOutcome {
     NoSuchElement(fantoccini::error::CmdError),
     EarlyEnd,
     Screenshot(String),
-    MatchUrlFail(String),
+    // stop requested while the step was waiting
+    Cancelled,
+    UnknownVariable(String),
+    MissingAttribute(String),
+    // price found, max allowed
+    PriceTooHigh(f64, f64),
+    PriceUnreadable(String),
+    // a Match or Assert step saw something else than it expected
+    AssertionFailed{ check: String, expected: String, actual: String },
+    InvalidPattern(String),
+    WaitTimedOut(String),
+    // a dialog is open that no step expected, carries its text
+    UnhandledAlert(String),
+    // a SendKeys text names a key that does not exist
+    InvalidKeys(String),
+    // the script threw or an async script never called back
+    ScriptError(String),
+    // a Notify step's notifier failed
+    NotifyFailed(String),
 
     // try restarting
     Timeout(tokio::time::Elapsed),
     Unexpected(fantoccini::error::CmdError),
     ClientLost,
     ReCaptchaIssue(String),
+    TabDoesNotExist(usize),
 }
 
 impl Error for BrowserOutcome {}
@@ -32,115 +90,162 @@ impl std::fmt::Display for BrowserOutcome {
             BrowserOutcome::EarlyEnd => {write!(f, "Manual end by step")},
             BrowserOutcome::ClientLost => {write!(f, "Client lost")},
             BrowserOutcome::Screenshot(name) => {write!(f, "Failed to take screenshot: ({})",name)},
-            BrowserOutcome::MatchUrlFail(name) => {write!(f, "Failed to match url: ({})",name)},
+            BrowserOutcome::Cancelled => {write!(f, "Cancelled")},
+            BrowserOutcome::UnknownVariable(name) => {write!(f, "Variable has no value: (${{{}}})",name)},
+            BrowserOutcome::MissingAttribute(name) => {write!(f, "Missing attribute: ({})",name)},
+            BrowserOutcome::PriceTooHigh(price, max) => {write!(f, "Price too high: ({} > {})",price,max)},
+            BrowserOutcome::PriceUnreadable(issue) => {write!(f, "Price unreadable: ({})",issue)},
+            BrowserOutcome::AssertionFailed{check, expected, actual} => {write!(f, "{} failed: (expected {}, found {})",check,expected,actual)},
+            BrowserOutcome::UnhandledAlert(text) => {write!(f, "Unhandled dialog: ({})",text)},
+            BrowserOutcome::InvalidKeys(issue) => {write!(f, "Invalid keys: ({})",issue)},
+            BrowserOutcome::ScriptError(issue) => {write!(f, "Script error: ({})",issue)},
+            BrowserOutcome::NotifyFailed(issue) => {write!(f, "Notification failed: ({})",issue)},
+            BrowserOutcome::WaitTimedOut(issue) => {write!(f, "Wait timed out: ({})",issue)},
+            BrowserOutcome::InvalidPattern(issue) => {write!(f, "Invalid pattern: ({})",issue)},
             BrowserOutcome::ReCaptchaIssue(issue) => {write!(f, "ReCaptcha issue: ({})",issue)},
+            BrowserOutcome::TabDoesNotExist(index) => {write!(f, "Browser tab does not exist: ({})",index)},
         }
     }
 }
 
-// there's an exposed API for switching tabs, this error is for that very specific case
-#[derive(Debug)]
-struct TabDoesNotExist;
-
-impl Error for TabDoesNotExist {}
-
-impl std::fmt::Display for TabDoesNotExist {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Browser tab does not exist")
+impl BrowserOutcome {
+    // the variant name, for the journal
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BrowserOutcome::NoSuchElement(_) => "NoSuchElement",
+            BrowserOutcome::EarlyEnd => "EarlyEnd",
+            BrowserOutcome::Screenshot(_) => "Screenshot",
+            BrowserOutcome::Cancelled => "Cancelled",
+            BrowserOutcome::UnknownVariable(_) => "UnknownVariable",
+            BrowserOutcome::MissingAttribute(_) => "MissingAttribute",
+            BrowserOutcome::PriceTooHigh(_, _) => "PriceTooHigh",
+            BrowserOutcome::PriceUnreadable(_) => "PriceUnreadable",
+            BrowserOutcome::AssertionFailed{..} => "AssertionFailed",
+            BrowserOutcome::InvalidPattern(_) => "InvalidPattern",
+            BrowserOutcome::WaitTimedOut(_) => "WaitTimedOut",
+            BrowserOutcome::UnhandledAlert(_) => "UnhandledAlert",
+            BrowserOutcome::InvalidKeys(_) => "InvalidKeys",
+            BrowserOutcome::ScriptError(_) => "ScriptError",
+            BrowserOutcome::NotifyFailed(_) => "NotifyFailed",
+            BrowserOutcome::Timeout(_) => "Timeout",
+            BrowserOutcome::Unexpected(_) => "Unexpected",
+            BrowserOutcome::ClientLost => "ClientLost",
+            BrowserOutcome::ReCaptchaIssue(_) => "ReCaptchaIssue",
+            BrowserOutcome::TabDoesNotExist(_) => "TabDoesNotExist",
+        }
     }
 }
 
 pub struct Browser {
-    // This option wrapping is needed due an API issue in fantoccini::Client::enter_parent_frame
+    // None between close() and start()
     client: Option<Client>,
+    // the geckodriver/chromedriver process started for the current client
+    driver: Option<DriverProcess>,
     timeout: Duration,
-    profile: String,
+    settings: DriverSettings,
     screenshot_path: String,
-    marionette_port: u64,
     timestamp: u64,
-    screenshot_counter: u64,
+    tabs: usize,
+    // the frames entered from the top window of the current tab, innermost last
+    frames: Vec<String>,
+    // for the commands fantoccini does not have
+    http: reqwest::Client,
 }
 
 impl Browser {
     pub async fn new(tabs: usize, 
         timeout: Duration, 
-        profile: &String,
-        screenshot_path: &String,
-        marionette_port: u64) -> Result<Browser, Box<dyn Error>> {
-
-        Browser::force_close_driver().await.ok();
-        Browser::force_close_firefox().await.ok();
+        settings: DriverSettings,
+        screenshot_path: &str) -> Result<Browser, Box<dyn Error>> {
 
         let mut browser = Browser {
-            //client: Client::new("http://localhost:4444").await?,
-            client: Some(Browser::new_client(profile,marionette_port).await?),
+            client: None,
+            driver: None,
             timeout,
-            profile: profile.clone(),
-            screenshot_path: screenshot_path.clone(),
-            marionette_port,
+            settings,
+            screenshot_path: screenshot_path.to_string(),
             timestamp: std::time::SystemTime::now()
                 .duration_since(std::time::SystemTime::UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
-            screenshot_counter: 0,
+            tabs,
+            frames: Vec::new(),
+            http: reqwest::Client::new(),
         };
-
-        for _ in 1..tabs {
-            Browser::handle_result(browser.get_client().await?.new_window(true),timeout).await?;
-        }
+        browser.start().await?;
 
         Ok(browser)
     }
 
     pub async fn restart(&mut self) -> Result<(), Box<dyn Error>> {        
         self.close().await?;
-        self.client = Some(Browser::new_client(&self.profile, self.marionette_port).await?);
-        Ok(())
+        self.start().await
     }
 
-    async fn new_client(profile: &String, marionette_port: u64) -> Result<Client, Box<dyn Error>> {
-        Command::new(".\\geckodriver.exe").
-        args(&["--marionette-port", marionette_port.to_string().as_str()])
-        .stdout(Stdio::null()).spawn()?;
-
-        let args = serde_json::json![{
-            "args": ["--profile", serde_json::value::Value::String(profile.clone())],
-        }];
-        let mut capabilities = webdriver::capabilities::Capabilities::new();
-        capabilities.insert("moz:firefoxOptions".to_string(), args);
+    // spawn the driver for the configured browser and open a new session on it
+    async fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        let settings = &self.settings;
+        let port = settings.driver_port;
+
+        let (driver_args, options_key, options) = match settings.browser {
+            BrowserKind::Firefox => {
+                let mut args = vec!["--profile".to_string(), settings.profile.clone()];
+                args.extend(settings.browser_args.iter().cloned());
+                (
+                    vec![
+                        "--port".to_string(), port.to_string(),
+                        "--marionette-port".to_string(), settings.marionette_port.to_string(),
+                    ],
+                    "moz:firefoxOptions",
+                    serde_json::json![{ "args": args }],
+                )
+            },
+            BrowserKind::Chrome => {
+                let mut args = vec![format!("--user-data-dir={}", settings.profile)];
+                if !settings.profile_directory.is_empty() {
+                    args.push(format!("--profile-directory={}", settings.profile_directory));
+                }
+                args.extend(settings.browser_args.iter().cloned());
+                (
+                    vec![format!("--port={}", port)],
+                    "goog:chromeOptions",
+                    serde_json::json![{ "args": args }],
+                )
+            },
+        };
 
-        Ok(Client::with_capabilities("http://localhost:4444",capabilities).await?)
-    }
+        let mut driver = DriverProcess::spawn(&settings.driver_path, &driver_args)?;
+        driver.wait_until_listening(port, self.timeout).await?;
 
-    pub async fn switch_tab(&mut self, index: usize) -> Result<(), Box<dyn Error>> {
+        let mut capabilities = webdriver::capabilities::Capabilities::new();
+        capabilities.insert(options_key.to_string(), options);
+        // leave dialogs open so steps and on_dialog can read and answer them,
+        // instead of the driver dismissing them
+        capabilities.insert("unhandledPromptBehavior".to_string(), serde_json::json!("ignore"));
+
+        // if the session cannot be created the driver is dropped, which kills it
+        let client = Client::with_capabilities(&format!("http://localhost:{}", port), capabilities).await?;
+        self.client = Some(client);
+        self.driver = Some(driver);
+        self.frames.clear();
+
+        // one tab per group, recreated on every restart
         let timeout = self.timeout;
-        let handle = Browser::handle_result(self.get_client().await?.windows(), timeout).await?.get(index).ok_or(TabDoesNotExist)?.clone();
-        Browser::handle_result(self.get_client().await?.switch_to_window(handle), timeout).await?;
+        for _ in 1..self.tabs {
+            Browser::handle_result(self.get_client().await?.new_window(true),timeout).await?;
+        }
         Ok(())
     }
 
+    // end the session, then kill the driver and the browser it launched.
+    // An unresponsive driver will not end the session, so the kill always runs
     pub async fn close(&mut self) -> Result<(), Box<dyn Error>> {
-        Browser::force_close_firefox().await?;
-        self.get_client().await?.close().await?;
-        Browser::force_close_driver().await?;
-        Ok(())
-    }
-
-    // Geckodriver sometimes becomes unresponsive after a long runtime
-    // and needs to be restarted
-    async fn force_close_driver() -> Result<(), Box<dyn Error>> {
-        Command::new("taskkill")
-        .args(&["/f", "/im", "geckodriver.exe"])
-        .output()?;
-        Ok(())
-    }
-
-    // When Geckodriver becomes unresponsive, Firefox needs to be closed so
-    // a new instance of Geckodriver can spawn a fresh Firefox process
-    async fn force_close_firefox() -> Result<(), Box<dyn Error>> {
-        Command::new("taskkill")
-        .args(&["/f", "/im", "Firefox.exe"])
-        .output()?;
+        if let Some(mut client) = std::mem::take(&mut self.client) {
+            Browser::handle_result(client.close(), self.timeout).await.ok();
+        }
+        if let Some(mut driver) = std::mem::take(&mut self.driver) {
+            driver.kill()?;
+        }
         Ok(())
     }
 
@@ -160,6 +265,10 @@ impl Browser {
                             no_elem @ fantoccini::error::CmdError::NoSuchElement(_) => {
                                 Err(BrowserOutcome::NoSuchElement(no_elem))
                             },
+                            // the session is fine, a dialog blocks it
+                            fantoccini::error::CmdError::Standard(ref alert) if alert.error == ErrorStatus::UnexpectedAlertOpen => {
+                                Err(BrowserOutcome::UnhandledAlert(alert.message.to_string()))
+                            },
                             any => {
                                 Err(BrowserOutcome::Unexpected(any))
                             }
@@ -173,88 +282,393 @@ impl Browser {
         }
     }
 
-    // get element by CSS selector
-    pub async fn find(&mut self, selector: &String) -> Result<fantoccini::Element, BrowserOutcome> {
+    // get the element a selector points at, the first match unless it has an index
+    async fn find_element(&mut self, selector: &Selector) -> Result<Element, BrowserOutcome> {
         let timeout = self.timeout;
-        Browser::handle_result(self.get_client().await?.find(Locator::Css(selector)),timeout).await
+        let query = locator::query(selector);
+        let locator = webdriver_locator(&query);
+        if selector.index == 0 {
+            let result = Browser::handle_result(self.get_client().await?.find(locator),timeout).await;
+            return self.lost_frame(result).await;
+        }
+        let result = Browser::handle_result(self.get_client().await?.find_all(locator),timeout).await;
+        let mut elements = self.lost_frame(result).await?;
+        if selector.index < elements.len() {
+            Ok(elements.swap_remove(selector.index))
+        } else {
+            Err(no_such_element(format!("{} only has {} match(es)", selector, elements.len())))
+        }
     }
 
-    // click element
-    pub async fn click(&mut self, selector: &String) -> Result<(), BrowserOutcome>  {
-        match Browser::handle_result(self.find(selector).await?.click(), self.timeout).await {
-            Ok(_) => {Ok(())},
-            Err(err) => {Err(err)}
+    // run a script that starts by calling locate(), see locator.rs
+    async fn execute_on(&mut self, selector: &Selector, script: &str, mut args: Vec<serde_json::Value>) -> Result<serde_json::Value, BrowserOutcome> {
+        let script = format!("{}const element = locate(arguments[0], arguments[1], arguments[2]);\n{}", LOCATE_JS, script);
+        let mut all = locator::script_args(selector);
+        all.append(&mut args);
+        self.execute(&script, all).await
+    }
+
+    // run JavaScript in the current frame and return its JSON result
+    async fn execute(&mut self, script: &str, args: Vec<serde_json::Value>) -> Result<serde_json::Value, BrowserOutcome> {
+        let timeout = self.timeout;
+        let result = Browser::handle_result(self.get_client().await?.execute(script, args), timeout).await;
+        self.lost_frame(result).await
+    }
+
+    // move the mouse to the centre of the element, then run the button presses.
+    // The element is scrolled into view first, a pointer cannot move outside
+    // of the viewport
+    async fn pointer_actions(&mut self, selector: &Selector, buttons: Vec<serde_json::Value>) -> Result<(), BrowserOutcome> {
+        self.scroll_into_view(selector).await?;
+        let element = self.find_element(selector).await?;
+        let origin = serde_json::to_value(&element)
+            .map_err(|err| no_such_element(format!("{} cannot be a pointer origin, {}", selector, err)))?;
+        let mut actions = vec![serde_json::json!({"type": "pointerMove", "duration": 0, "origin": origin, "x": 0, "y": 0})];
+        actions.extend(buttons);
+        let body = serde_json::json!({"actions": [{
+            "type": "pointer",
+            "id": MOUSE,
+            "parameters": {"pointerType": "mouse"},
+            "actions": actions,
+        }]});
+
+        let before = self.url_in_frame().await?;
+        let result = self.command(reqwest::Method::POST, "actions", Some(body)).await;
+        // release whatever is still pressed, also when the sequence stopped halfway
+        if let Err(err) = self.command(reqwest::Method::DELETE, "actions", None).await {
+            tracing::debug!(error = %err, "could not release the actions");
+        }
+        self.lost_frame(result).await?;
+        self.reset_navigated_frames(before).await
+    }
+
+    // a page that cannot run the hook only loses its console log
+    async fn hook_console(&mut self) {
+        if let Err(err) = self.execute(CONSOLE_HOOK_JS, vec![]).await {
+            tracing::debug!(error = %err, "console hook not installed");
         }
     }
 
-    // get element by CSS selector and insert a value as a direct child
-    pub async fn insert(&mut self, selector: &String, value: &String) -> Result<(), BrowserOutcome>  {
+    // a navigation of the page around the current frame discards the frame,
+    // and every command after it fails. Depending on the driver that is a
+    // missing frame, window or element. Go back to the top window and fail
+    // the step like a missing element, so retries and optional steps work
+    // on the new page instead of restarting the browser
+    async fn lost_frame<X: Send>(&mut self, result: Result<X, BrowserOutcome>) -> Result<X, BrowserOutcome> {
+        if self.frames.is_empty() {
+            return result;
+        }
+        let lost = match &result {
+            Err(BrowserOutcome::Unexpected(CmdError::Standard(err))) => frame_gone(&err.error),
+            // the frame may only be missing the element, ask the frame itself
+            Err(BrowserOutcome::NoSuchElement(_)) => !self.frame_alive().await,
+            _ => false,
+        };
+        if !lost {
+            return result;
+        }
+        let frames = self.frames.join(" > ");
+        self.top_window().await?;
+        Err(no_such_element(format!("frame {} was discarded by a navigation, back in the top window", frames)))
+    }
+
+    // any other error is left to the command that follows
+    async fn frame_alive(&mut self) -> bool {
         let timeout = self.timeout;
+        let client = match self.get_client().await {
+            Ok(client) => client,
+            Err(_) => return true,
+        };
+        match Browser::handle_result(client.execute("return true;", vec![]), timeout).await {
+            Err(BrowserOutcome::Unexpected(CmdError::Standard(err))) => !frame_gone(&err.error),
+            _ => true,
+        }
+    }
 
-        match Browser::handle_result(self.get_client().await?.form(Locator::Css("html")),timeout).await {
-            Ok(mut val) => {
-                match Browser::handle_result(val.set(Locator::Css(selector), value),self.timeout).await {         
-                    Ok(_) => {Ok(())},
-                    Err(err) => {Err(err)}
-                }
-            },
-            Err(err) => {Err(err)}
+    // the top level URL, without its fragment, while in a frame. Outside of
+    // one a navigation leaves no frame path to reset
+    async fn url_in_frame(&mut self) -> Result<Option<url::Url>, BrowserOutcome> {
+        if self.frames.is_empty() {
+            return Ok(None);
+        }
+        let timeout = self.timeout;
+        let mut url = Browser::handle_result(self.get_client().await?.current_url(), timeout).await?;
+        url.set_fragment(None);
+        Ok(Some(url))
+    }
+
+    // a click that navigated the top level page discarded the frames it was in,
+    // the tab is back in its top window like after goto
+    async fn reset_navigated_frames(&mut self, before: Option<url::Url>) -> Result<(), BrowserOutcome> {
+        let before = match before {
+            Some(before) => before,
+            None => return Ok(()),
+        };
+        let timeout = self.timeout;
+        let mut after = match Browser::handle_result(self.get_client().await?.current_url(), timeout).await {
+            Ok(after) => after,
+            Err(err) => return self.lost_frame(Err(err)).await,
+        };
+        after.set_fragment(None);
+        if after != before {
+            tracing::debug!(from = %before, to = %after, frames = %self.frames.join(" > "), "click navigated, back in the top window");
+            self.top_window().await?;
+        }
+        Ok(())
+    }
+
+    // enter_frame and enter_parent_frame take the Client by value, so they
+    // get a clone of the handle and the one kept here stays usable when they fail
+    async fn frame_command<F, T>(&mut self, command: F) -> Result<(), BrowserOutcome>
+    where F: FnOnce(Client) -> T, T: std::future::Future<Output=Result<Client, CmdError>> {
+        let client = self.get_client().await?.clone();
+        self.client = Some(Browser::handle_result(command(client), self.timeout).await?);
+        Ok(())
+    }
+
+    // WebDriver returns the visible part of the page as PNG bytes, they are not raw pixels
+    async fn capture_viewport(&mut self) -> Result<RgbaImage, BrowserOutcome> {
+        let timeout = self.timeout;
+        let png = Browser::handle_result(self.get_client().await?.screenshot(), timeout).await?;
+        match image::load_from_memory_with_format(&png, image::ImageFormat::Png) {
+            Ok(image) => Ok(image.into_rgba8()),
+            Err(err) => Err(BrowserOutcome::Screenshot(format!("could not decode PNG, {}", err))),
+        }
+    }
+
+    // WebDriver only captures the viewport, so scroll down the page one
+    // viewport at a time and stitch the captures together
+    async fn capture_full_page(&mut self) -> Result<RgbaImage, BrowserOutcome> {
+        let metrics = self.execute(
+            "return [window.scrollX, window.scrollY, document.documentElement.scrollHeight, window.innerHeight];",
+            vec![],
+        ).await?;
+        let metrics = numbers(&metrics, 4)?;
+        let (start_x, start_y, page_height, viewport_height) = (metrics[0], metrics[1], metrics[2], metrics[3]);
+
+        let mut canvas: Option<RgbaImage> = None;
+        let mut offset = 0.0;
+        let mut previous = -1.0;
+        loop {
+            let scrolled = self.execute(
+                "window.scrollTo(0, arguments[0]); return window.scrollY;",
+                vec![serde_json::json!(offset)],
+            ).await?.as_f64().unwrap_or(offset);
+            // the page stopped scrolling, everything has been captured
+            if scrolled <= previous {
+                break;
+            }
+            previous = scrolled;
+
+            let piece = self.capture_viewport().await?;
+            // captures are in device pixels, scroll offsets in CSS pixels
+            let scale = piece.height() as f64 / viewport_height.max(1.0);
+            let canvas = canvas.get_or_insert_with(|| {
+                let height = ((page_height * scale) as u32).min(MAX_FULL_PAGE_HEIGHT).max(piece.height());
+                RgbaImage::new(piece.width(), height)
+            });
+            let y = (scrolled * scale) as u32;
+            if y >= canvas.height() {
+                break;
+            }
+            image::imageops::replace(canvas, &piece, 0, y);
+
+            offset = scrolled + viewport_height;
+            if offset >= page_height {
+                break;
+            }
+        }
+
+        self.execute(
+            "window.scrollTo(arguments[0], arguments[1]);",
+            vec![serde_json::json!(start_x), serde_json::json!(start_y)],
+        ).await?;
+
+        match canvas {
+            Some(canvas) => Ok(canvas),
+            None => Err(BrowserOutcome::Screenshot("page could not be captured".to_string())),
         }
     }
 
+    // scroll the element into view and crop its bounding box out of the viewport
+    async fn capture_element(&mut self, selector: &Selector) -> Result<RgbaImage, BrowserOutcome> {
+        // fail the same way every other step does when the element is missing
+        self.find_element(selector).await?;
+        let rect = self.execute_on(selector,
+            "element.scrollIntoView({block: 'center', inline: 'center'});
+            const rect = element.getBoundingClientRect();
+            return [rect.left, rect.top, rect.width, rect.height, window.innerWidth];",
+            vec![],
+        ).await?;
+        let rect = numbers(&rect, 5)?;
+
+        let viewport = self.capture_viewport().await?;
+        let scale = viewport.width() as f64 / rect[4].max(1.0);
+        let x = ((rect[0] * scale).max(0.0) as u32).min(viewport.width());
+        let y = ((rect[1] * scale).max(0.0) as u32).min(viewport.height());
+        let width = ((rect[2] * scale) as u32).min(viewport.width() - x);
+        let height = ((rect[3] * scale) as u32).min(viewport.height() - y);
+        if width == 0 || height == 0 {
+            return Err(BrowserOutcome::Screenshot(format!("{} is not visible", selector)));
+        }
+
+        Ok(image::imageops::crop_imm(&viewport, x, y, width, height).to_image())
+    }
+
+    // <screenshot_path>/<start time>-<counter>-<name>.png
+    fn screenshot_file(&self, name: &str) -> PathBuf {
+        let name = file_name(name);
+        let counter = SCREENSHOT_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let file = if name.is_empty() {
+            format!("{}-{}.png", self.timestamp, counter)
+        } else {
+            format!("{}-{}-{}.png", self.timestamp, counter, name)
+        };
+        Path::new(&self.screenshot_path).join(file)
+    }
+
+    // fantoccini 0.14 has no alert or actions commands, they are sent to the
+    // driver directly for the client's session. WebDriver errors come back
+    // as fantoccini's so handle_result sorts them like any other
+    async fn command(&mut self, method: reqwest::Method, command: &str, body: Option<serde_json::Value>) -> Result<serde_json::Value, BrowserOutcome> {
+        let timeout = self.timeout;
+        let session = match Browser::handle_result(self.get_client().await?.session_id(), timeout).await? {
+            Some(session) => session,
+            None => return Err(BrowserOutcome::ClientLost),
+        };
+        let url = format!("http://localhost:{}/session/{}/{}", self.settings.driver_port, session, command);
+        let mut request = self.http.request(method, &url);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        Browser::handle_result(send_command(request), timeout).await
+    }
+
+    // ClientLost between close() and start()
+    async fn get_client(&mut self) -> Result<&mut Client, BrowserOutcome> {
+        match &mut self.client {
+            Some(val) => {Ok(val)},
+            None => { Err(BrowserOutcome::ClientLost) }
+        }
+    }
+}
+
+#[async_trait]
+impl BrowserBackend for Browser {
     // navigate tab to URL
-    pub async fn goto(&mut self, dest: &String) -> Result<(), BrowserOutcome>  {
+    async fn goto(&mut self, dest: &str) -> Result<(), BrowserOutcome>  {
         let timeout = self.timeout;
         match Browser::handle_result(self.get_client().await?.goto(dest),timeout).await {
-            Ok(_) => {Ok(())},
+            // navigating always leaves the tab in its top window
+            Ok(_) => {self.frames.clear(); self.hook_console().await; Ok(())},
             Err(err) => {Err(err)}
         }
     }
 
-    // refresh the page
-    pub async fn refresh(&mut self) -> Result<(), BrowserOutcome>  {
-        let timeout = self.timeout;
-        match Browser::handle_result(self.get_client().await?.refresh(),timeout).await {
-            Ok(_) => {Ok(())},
+    // check an element exists
+    async fn find(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
+        self.find_element(selector).await?;
+        Ok(())
+    }
+
+    // click element
+    async fn click(&mut self, selector: &Selector) -> Result<(), BrowserOutcome>  {
+        let before = self.url_in_frame().await?;
+        let result = Browser::handle_result(self.find_element(selector).await?.click(), self.timeout).await;
+        match self.lost_frame(result).await {
+            Ok(_) => self.reset_navigated_frames(before).await,
             Err(err) => {Err(err)}
         }
     }
 
-    // find element by selector and attempt to get an attribute value from the selector
-    pub async fn find_attribute(&mut self, selector: &String, attr: &String) -> Result<Option<String>, BrowserOutcome> {
-        Browser::handle_result(self.find(selector).await?.attr(attr), self.timeout).await
+    // get element by selector and set its value, like fantoccini's Form::set
+    async fn insert(&mut self, selector: &Selector, value: &str) -> Result<(), BrowserOutcome>  {
+        self.find_element(selector).await?;
+        self.execute_on(selector, "element.value = arguments[3];", vec![serde_json::json!(value)]).await?;
+        Ok(())
     }
 
-    // used to take a screenshot of the current page
-    // this method is not working currently. The pixels vec size seems unrelated to
-    // the actual size of of the screen, so (width * height == pixels.len()) is false
-    pub async fn screenshot(&mut self) -> Result<(), BrowserOutcome> {
+    async fn clear(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
         let timeout = self.timeout;
+        let mut element = self.find_element(selector).await?;
+        Browser::handle_result(element.clear(), timeout).await
+    }
+
+    async fn send_keys(&mut self, selector: &Selector, keys: &str) -> Result<(), BrowserOutcome> {
+        let timeout = self.timeout;
+        let mut element = self.find_element(selector).await?;
+        Browser::handle_result(element.send_keys(keys), timeout).await
+    }
 
-        let full_path = format!("{}{}-{}.png", self.screenshot_path, self.timestamp, self.screenshot_counter);
-        let (width,height) = Browser::handle_result(self.get_client().await?.get_window_size(),timeout).await?;
-        let pixels = Browser::handle_result(self.get_client().await?.screenshot(),timeout).await?;
-        let image = match image::RgbImage::from_raw(width as u32, height as u32, pixels) {
-            Some(val) => val,
-            None => {
-                return Err(BrowserOutcome::Screenshot(
-                    format!("{}, {}",full_path.clone(),"raw conversion failed")
-                ));
+    // WebDriver can only select by value or index, an option text is looked up first
+    async fn select(&mut self, selector: &Selector, option: &SelectBy) -> Result<(), BrowserOutcome> {
+        let timeout = self.timeout;
+        let value = match option {
+            SelectBy::Value(value) => value.clone(),
+            SelectBy::Index(index) => {
+                let element = self.find_element(selector).await?;
+                Browser::handle_result(element.select_by_index(*index), timeout).await?;
+                return Ok(());
             },
-        };
-        match image.save(full_path.clone()) {
-            Err(err) => {
-                return Err(BrowserOutcome::Screenshot(
-                    format!("{}, {}",full_path.clone(),err))
-                );
+            SelectBy::Text(text) => {
+                self.find_element(selector).await?;
+                let value = self.execute_on(selector,
+                    "const text = arguments[3].trim().replace(/\\s+/g, ' ');
+                    const option = Array.from(element.options || [])
+                        .find(option => option.text.trim().replace(/\\s+/g, ' ') === text);
+                    return option ? option.value : null;",
+                    vec![serde_json::json!(text)],
+                ).await?;
+                match value.as_str() {
+                    Some(value) => value.to_string(),
+                    None => return Err(no_such_element(format!("{} has no option \"{}\"", selector, text))),
+                }
             },
-            _ => {self.screenshot_counter += 1;}
+        };
+        let element = self.find_element(selector).await?;
+        Browser::handle_result(element.select_by_value(&value), timeout).await?;
+        Ok(())
+    }
+
+    async fn set_checked(&mut self, selector: &Selector, checked: bool) -> Result<(), BrowserOutcome> {
+        self.find_element(selector).await?;
+        let current = self.execute_on(selector, "return element.checked === true;", vec![]).await?;
+        if current.as_bool() != Some(checked) {
+            self.click(selector).await?;
         }
         Ok(())
     }
 
+    async fn hover(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
+        self.pointer_actions(selector, vec![]).await
+    }
+
+    async fn scroll_into_view(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
+        self.find_element(selector).await?;
+        self.execute_on(selector, "element.scrollIntoView({block: 'center', inline: 'center'});", vec![]).await?;
+        Ok(())
+    }
+
+    async fn double_click(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
+        self.pointer_actions(selector, [button_press(0), button_press(0)].concat()).await
+    }
+
+    async fn right_click(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
+        self.pointer_actions(selector, button_press(2)).await
+    }
+
+    // find element by selector and attempt to get an attribute value from the selector
+    async fn find_attribute(&mut self, selector: &Selector, attr: &str) -> Result<Option<String>, BrowserOutcome> {
+        Browser::handle_result(self.find_element(selector).await?.attr(attr), self.timeout).await
+    }
+
+    // visible text of the element
+    async fn find_text(&mut self, selector: &Selector) -> Result<String, BrowserOutcome> {
+        Browser::handle_result(self.find_element(selector).await?.text(), self.timeout).await
+    }
+
     // get the current url
-    pub async fn current_url(&mut self) -> Result<String, BrowserOutcome> {
+    async fn current_url(&mut self) -> Result<String, BrowserOutcome> {
         let timeout = self.timeout;
 
         Ok(Browser::handle_result(
@@ -262,30 +676,232 @@ impl Browser {
         ).await?.to_string())
     }
 
-    // this method is needed because fantoccini:Client:enter_parent_frame()
-    // cannot accept a mutable reference to Client, unlike the rest
-    // of the API, so we take ownership of Client and set the Option
-    // to None temporarily in top_window() so we can use enter_parent_frame()
-    async fn get_client(&mut self) -> Result<&mut Client, BrowserOutcome> {
-        match &mut self.client {
-            Some(val) => {Ok(val)},
-            None => { Err(BrowserOutcome::ClientLost) }
+    // errors thrown by the script are the script's fault, not the session's,
+    // so they do not restart the browser
+    async fn run_script(&mut self, source: &str, args: Vec<serde_json::Value>, is_async: bool) -> Result<serde_json::Value, BrowserOutcome> {
+        let result = if is_async {
+            let timeout = self.timeout;
+            let result = Browser::handle_result(self.get_client().await?.execute_async(source, args), timeout).await;
+            self.lost_frame(result).await
+        } else {
+            self.execute(source, args).await
+        };
+        match result {
+            Err(BrowserOutcome::Unexpected(CmdError::Standard(err)))
+                if err.error == ErrorStatus::JavascriptError || err.error == ErrorStatus::ScriptTimeout => {
+                Err(BrowserOutcome::ScriptError(err.message.to_string()))
+            },
+            result => result,
+        }
+    }
+
+    async fn dialog_text(&mut self) -> Result<Option<String>, BrowserOutcome> {
+        match self.command(reqwest::Method::GET, "alert/text", None).await {
+            Ok(text) => Ok(Some(text.as_str().unwrap_or_default().to_string())),
+            Err(BrowserOutcome::Unexpected(CmdError::Standard(err))) if err.error == ErrorStatus::NoSuchAlert => Ok(None),
+            Err(err) => Err(err),
         }
     }
 
-    // temporarily takes ownership of Client to use fantoccini:Client:enter_parent_frame()
-    pub async fn top_window(&mut self) -> Result<(), BrowserOutcome> {
-        let client = match std::mem::take(&mut self.client) {
-            Some(val) => {val},
-            None => { return Err(BrowserOutcome::ClientLost) }
+    async fn answer_dialog(&mut self, action: DialogAction, text: &str) -> Result<(), BrowserOutcome> {
+        if !text.is_empty() {
+            self.command(reqwest::Method::POST, "alert/text", Some(serde_json::json!({ "text": text }))).await?;
+        }
+        let command = match action {
+            DialogAction::Accept => "alert/accept",
+            DialogAction::Dismiss => "alert/dismiss",
         };
-        self.client = Some(Browser::handle_result(client.enter_parent_frame(), self.timeout).await?);
+        self.command(reqwest::Method::POST, command, Some(serde_json::json!({}))).await?;
         Ok(())
     }
 
-    // change IFRAME using the passed element as the target
-    pub async fn switch_frame(&mut self,element: Element) -> Result<(), BrowserOutcome> {
+    async fn page_source(&mut self) -> Result<String, BrowserOutcome> {
+        let timeout = self.timeout;
+        Browser::handle_result(self.get_client().await?.source(), timeout).await
+    }
+
+    async fn window_handles(&mut self) -> Result<Vec<String>, BrowserOutcome> {
+        let timeout = self.timeout;
+        let handles = Browser::handle_result(self.get_client().await?.windows(), timeout).await?;
+        Ok(handles.into_iter().map(|handle| handle.0).collect())
+    }
+
+    async fn console_log(&mut self) -> Result<Option<Vec<String>>, BrowserOutcome> {
+        let entries = self.execute("return window.__scraperConsole || null;", vec![]).await?;
+        Ok(match entries {
+            serde_json::Value::Array(entries) => Some(entries.into_iter()
+                .map(|entry| match entry {
+                    serde_json::Value::String(entry) => entry,
+                    other => other.to_string(),
+                })
+                .collect()),
+            _ => None,
+        })
+    }
+
+    async fn ready_state(&mut self) -> Result<String, BrowserOutcome> {
+        let state = self.execute("return document.readyState;", vec![]).await?;
+        Ok(state.as_str().unwrap_or_default().to_string())
+    }
+
+    async fn title(&mut self) -> Result<String, BrowserOutcome> {
+        let title = self.execute("return document.title;", vec![]).await?;
+        Ok(title.as_str().unwrap_or_default().to_string())
+    }
+
+    // same rule as WebDriver's: an element without any box is not displayed
+    async fn is_displayed(&mut self, selector: &Selector) -> Result<bool, BrowserOutcome> {
+        self.find_element(selector).await?;
+        let displayed = self.execute_on(selector,
+            "const style = window.getComputedStyle(element);
+            return style.visibility !== 'hidden' && element.getClientRects().length > 0;",
+            vec![],
+        ).await?;
+        Ok(displayed.as_bool().unwrap_or(false))
+    }
+
+    async fn is_enabled(&mut self, selector: &Selector) -> Result<bool, BrowserOutcome> {
+        self.find_element(selector).await?;
+        let enabled = self.execute_on(selector, "return !element.matches(':disabled');", vec![]).await?;
+        Ok(enabled.as_bool().unwrap_or(false))
+    }
+
+    async fn count(&mut self, selector: &Selector) -> Result<usize, BrowserOutcome> {
+        let timeout = self.timeout;
+        let query = locator::query(selector);
+        let locator = webdriver_locator(&query);
+        let result = Browser::handle_result(self.get_client().await?.find_all(locator), timeout).await;
+        Ok(self.lost_frame(result).await?.len())
+    }
+
+    // refresh the page
+    async fn refresh(&mut self) -> Result<(), BrowserOutcome>  {
+        let timeout = self.timeout;
+        match Browser::handle_result(self.get_client().await?.refresh(),timeout).await {
+            Ok(_) => {self.frames.clear(); self.hook_console().await; Ok(())},
+            Err(err) => {Err(err)}
+        }
+    }
+
+    // change IFRAME using the element found by selector as the target
+    async fn switch_frame(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
+        let element = self.find_element(selector).await?;
         Browser::handle_result(element.enter_frame(), self.timeout).await?;
+        self.frames.push(selector.to_string());
+        Ok(())
+    }
+
+    async fn switch_frame_index(&mut self, index: usize) -> Result<(), BrowserOutcome> {
+        self.frame_command(|client| client.enter_frame(Some(index as u16))).await?;
+        self.frames.push(format!("frame {}", index));
+        Ok(())
+    }
+
+    async fn parent_frame(&mut self) -> Result<(), BrowserOutcome> {
+        self.frame_command(|client| client.enter_parent_frame()).await?;
+        self.frames.pop();
+        Ok(())
+    }
+
+    async fn top_window(&mut self) -> Result<(), BrowserOutcome> {
+        self.frame_command(|client| client.enter_frame(None)).await?;
+        self.frames.clear();
+        Ok(())
+    }
+
+    fn frames(&self) -> Vec<String> {
+        self.frames.clone()
+    }
+
+    async fn screenshot(&mut self, full_page: bool, selector: Option<&Selector>, name: &str) -> Result<String, BrowserOutcome> {
+        let image = match selector {
+            Some(selector) => self.capture_element(selector).await?,
+            None if full_page => self.capture_full_page().await?,
+            None => self.capture_viewport().await?,
+        };
+
+        let path = self.screenshot_file(name);
+        if let Err(err) = std::fs::create_dir_all(&self.screenshot_path) {
+            return Err(BrowserOutcome::Screenshot(format!("{}, {}", self.screenshot_path, err)));
+        }
+        if let Err(err) = image.save(&path) {
+            return Err(BrowserOutcome::Screenshot(format!("{}, {}", path.display(), err)));
+        }
+        Ok(path.display().to_string())
+    }
+
+    async fn switch_tab(&mut self, index: usize) -> Result<(), BrowserOutcome> {
+        let timeout = self.timeout;
+        let handle = Browser::handle_result(self.get_client().await?.windows(), timeout).await?
+            .get(index).ok_or(BrowserOutcome::TabDoesNotExist(index))?.clone();
+        Browser::handle_result(self.get_client().await?.switch_to_window(handle), timeout).await?;
+        // a window is always entered at its top
+        self.frames.clear();
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+fn webdriver_locator(query: &Query) -> Locator<'_> {
+    match query {
+        Query::Css(query) => Locator::Css(query),
+        Query::XPath(query) => Locator::XPath(query),
+    }
+}
+
+// pointerDown and pointerUp of one button, 0 is the main and 2 the secondary
+// one. Two presses in a row are a double click
+fn button_press(button: u8) -> Vec<serde_json::Value> {
+    vec![
+        serde_json::json!({"type": "pointerDown", "button": button}),
+        serde_json::json!({"type": "pointerUp", "button": button}),
+    ]
+}
+
+// what a command in a frame a navigation discarded fails with
+fn frame_gone(status: &ErrorStatus) -> bool {
+    matches!(status, ErrorStatus::NoSuchFrame | ErrorStatus::NoSuchWindow)
+}
+
+// the error a missing element gives, for misses found without WebDriver's help
+pub fn no_such_element(description: String) -> BrowserOutcome {
+    BrowserOutcome::NoSuchElement(CmdError::NoSuchElement(WebDriverError::new(ErrorStatus::NoSuchElement, description)))
+}
+
+// a step or group name made safe for file names, anything but ASCII letters,
+// digits, - and _ becomes _
+pub fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+// the value of a WebDriver reply, or its error the way fantoccini reports it
+async fn send_command(request: reqwest::RequestBuilder) -> Result<serde_json::Value, CmdError> {
+    let response = request.send().await
+        .map_err(|err| CmdError::Lost(std::io::Error::other(err.to_string())))?;
+    let text = response.text().await
+        .map_err(|err| CmdError::Lost(std::io::Error::other(err.to_string())))?;
+    let mut reply: serde_json::Value = serde_json::from_str(&text).map_err(|_| CmdError::NotJson(text))?;
+    let value = match reply.get_mut("value") {
+        Some(value) => value.take(),
+        None => return Err(CmdError::NotW3C(reply)),
+    };
+    match value.get("error").and_then(|error| error.as_str()) {
+        Some(error) => {
+            let message = value.get("message").and_then(|message| message.as_str()).unwrap_or_default();
+            Err(CmdError::Standard(WebDriverError::new(ErrorStatus::from(error.to_string()), message.to_string())))
+        },
+        None => Ok(value),
+    }
+}
+
+// read a JSON array of numbers returned by a script
+fn numbers(value: &serde_json::Value, count: usize) -> Result<Vec<f64>, BrowserOutcome> {
+    let values: Vec<f64> = value.as_array()
+        .map(|array| array.iter().filter_map(|number| number.as_f64()).collect())
+        .unwrap_or_default();
+    if values.len() != count {
+        return Err(BrowserOutcome::Screenshot(format!("unexpected script result {}", value)));
+    }
+    Ok(values)
+}
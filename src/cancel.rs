@@ -0,0 +1,149 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use crate::browser::BrowserOutcome;
+
+// a cloneable stop flag that wakes everything waiting on it the moment it is
+// set. A child token is cancelled along with its parent, cancelling a child
+// leaves the parent and its other children running
+#[derive(Clone)]
+pub struct CancelToken {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    sender: watch::Sender<bool>,
+    receiver: watch::Receiver<bool>,
+    parent: Option<CancelToken>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::with_parent(None)
+    }
+
+    pub fn child(&self) -> CancelToken {
+        CancelToken::with_parent(Some(self.clone()))
+    }
+
+    fn with_parent(parent: Option<CancelToken>) -> CancelToken {
+        let (sender, receiver) = watch::channel(false);
+        CancelToken { inner: Arc::new(Inner { sender, receiver, parent }) }
+    }
+
+    pub fn cancel(&self) {
+        // only fails without receivers, and this token keeps one
+        self.inner.sender.broadcast(true).ok();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.inner.receiver.borrow() || self.inner.parent.as_ref().is_some_and(|parent| parent.is_cancelled())
+    }
+
+    // resolves once this token or any parent is cancelled. Boxed because
+    // waiting on the parent makes it recursive
+    pub fn cancelled(&self) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(async move {
+            let mut receiver = self.inner.receiver.clone();
+            let own = async move {
+                while let Some(cancelled) = receiver.recv().await {
+                    if cancelled {
+                        return;
+                    }
+                }
+                std::future::pending::<()>().await
+            };
+            match &self.inner.parent {
+                Some(parent) => {
+                    tokio::select! {
+                        _ = own => {},
+                        _ = parent.cancelled() => {},
+                    }
+                },
+                None => own.await,
+            }
+        })
+    }
+
+    // sleep that ends early with BrowserOutcome::Cancelled
+    pub async fn sleep(&self, duration: Duration) -> Result<(), BrowserOutcome> {
+        if self.is_cancelled() {
+            return Err(BrowserOutcome::Cancelled);
+        }
+        tokio::select! {
+            _ = tokio::time::delay_for(duration) => Ok(()),
+            _ = self.cancelled() => Err(BrowserOutcome::Cancelled),
+        }
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> CancelToken {
+        CancelToken::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn parent_cancels_its_children() {
+        let parent = CancelToken::new();
+        let child = parent.child();
+        let grandchild = child.child();
+        parent.cancel();
+        assert!(child.is_cancelled());
+        assert!(grandchild.is_cancelled());
+    }
+
+    #[test]
+    fn child_leaves_the_parent_and_siblings_running() {
+        let parent = CancelToken::new();
+        let child = parent.child();
+        let sibling = parent.child();
+        child.cancel();
+        assert!(child.is_cancelled());
+        assert!(!parent.is_cancelled());
+        assert!(!sibling.is_cancelled());
+    }
+
+    #[test]
+    fn clones_share_the_flag() {
+        let token = CancelToken::new();
+        token.clone().cancel();
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn sleep_runs_out_without_a_cancel() {
+        let token = CancelToken::new();
+        assert!(token.sleep(Duration::from_millis(10)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn sleep_on_a_cancelled_token_returns_at_once() {
+        let token = CancelToken::new();
+        token.cancel();
+        assert!(matches!(token.sleep(Duration::from_secs(60)).await, Err(BrowserOutcome::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn cancel_interrupts_a_sleep() {
+        let parent = CancelToken::new();
+        let child = parent.child();
+        let start = Instant::now();
+        let canceller = parent.clone();
+        tokio::spawn(async move {
+            tokio::time::delay_for(Duration::from_millis(20)).await;
+            canceller.cancel();
+        });
+        assert!(matches!(child.sleep(Duration::from_secs(60)).await, Err(BrowserOutcome::Cancelled)));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
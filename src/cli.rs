@@ -0,0 +1,61 @@
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "scraper", about = "Automates browser steps described in a sites.toml config")]
+pub struct Options {
+    #[structopt(long, default_value = "sites.toml", help = "Path of the config file")]
+    pub config: String,
+
+    #[structopt(long = "group", number_of_values = 1, help = "Only run the named group, can be repeated")]
+    pub groups: Vec<String>,
+
+    #[structopt(long, help = "Run each group a single pass, then exit")]
+    pub once: bool,
+
+    #[structopt(long, help = "Stop after this many passes over the groups")]
+    pub max_iterations: Option<u64>,
+
+    #[structopt(long, help = "Run the groups once against the in-memory mock backend, no browser is started")]
+    pub dry_run: bool,
+
+    #[structopt(long, help = "Validate the config and exit")]
+    pub check: bool,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+// reading the run journal instead of running the groups
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    #[structopt(about = "Summarize a run recorded in the journal")]
+    Summary {
+        #[structopt(long, help = "Journal file, defaults to the journal set in the config")]
+        journal: Option<String>,
+
+        #[structopt(long, help = "Run to summarize (its start time as recorded), defaults to the last one")]
+        run: Option<u64>,
+    },
+    #[structopt(about = "Print the last entries of the journal")]
+    Tail {
+        #[structopt(long, help = "Journal file, defaults to the journal set in the config")]
+        journal: Option<String>,
+
+        #[structopt(short = "n", long, default_value = "20", help = "Number of entries to print")]
+        lines: usize,
+
+        #[structopt(short, long, help = "Keep printing entries as they are appended")]
+        follow: bool,
+    },
+}
+
+impl Options {
+    // None keeps looping until a group ends OK
+    pub fn iterations(&self) -> Option<u64> {
+        if self.once {
+            Some(1)
+        } else {
+            self.max_iterations
+        }
+    }
+}
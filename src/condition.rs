@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+// what happened to a named step during the current pass of its group.
+// Steps that have not been reached yet have no status at all
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepStatus {
+    Succeeded,
+    // only optional steps fail without ending the group
+    Failed,
+    // the step's condition was false so it did not run
+    Skipped,
+}
+
+// a parsed `when` expression, e.g. "buy_now && !cart_early || place_order_frame"
+//
+//   name          the step ran and succeeded, same as ok(name)
+//   failed(name)  the step ran and failed
+//   skipped(name) the step was reached but its condition was false
+//   ran(name)     the step ran, whether it succeeded or failed
+//   !a  a && b  a || b  (a)
+//
+// ! binds tightest, then &&, then ||. A step that has not been reached yet
+// is neither succeeded, failed, skipped nor ran, so `!name` is true for it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Succeeded(String),
+    Failed(String),
+    Skipped(String),
+    Ran(String),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Condition, ConditionError> {
+        let mut parser = Parser { tokens: tokenize(source)?, position: 0 };
+        let condition = parser.or()?;
+        match parser.peek() {
+            None => Ok(condition),
+            Some((token, offset)) => Err(ConditionError::new(*offset, format!("unexpected {}", token))),
+        }
+    }
+
+    // the old if_cond/if_not_cond fields, either one being true runs the step
+    pub fn from_legacy(if_cond: &str, if_not_cond: &str) -> Option<Condition> {
+        let if_cond = if if_cond.is_empty() { None } else { Some(Condition::Succeeded(if_cond.to_string())) };
+        let if_not_cond = if if_not_cond.is_empty() { None } else { Some(Condition::Failed(if_not_cond.to_string())) };
+        match (if_cond, if_not_cond) {
+            (Some(a), Some(b)) => Some(Condition::Or(Box::new(a), Box::new(b))),
+            (Some(a), None) => Some(a),
+            (None, Some(b)) => Some(b),
+            (None, None) => None,
+        }
+    }
+
+    pub fn evaluate(&self, results: &HashMap<String, StepStatus>) -> bool {
+        match self {
+            Condition::Succeeded(name) => results.get(name) == Some(&StepStatus::Succeeded),
+            Condition::Failed(name) => results.get(name) == Some(&StepStatus::Failed),
+            Condition::Skipped(name) => results.get(name) == Some(&StepStatus::Skipped),
+            Condition::Ran(name) => matches!(results.get(name), Some(StepStatus::Succeeded) | Some(StepStatus::Failed)),
+            Condition::Not(inner) => !inner.evaluate(results),
+            Condition::And(a, b) => a.evaluate(results) && b.evaluate(results),
+            Condition::Or(a, b) => a.evaluate(results) || b.evaluate(results),
+        }
+    }
+
+    // every step name the expression refers to
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            Condition::Succeeded(name)
+            | Condition::Failed(name)
+            | Condition::Skipped(name)
+            | Condition::Ran(name) => vec![name.as_str()],
+            Condition::Not(inner) => inner.names(),
+            Condition::And(a, b) | Condition::Or(a, b) => {
+                let mut names = a.names();
+                names.extend(b.names());
+                names
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConditionError {
+    // byte offset into the expression
+    pub offset: usize,
+    pub message: String,
+}
+
+impl ConditionError {
+    fn new(offset: usize, message: String) -> ConditionError {
+        ConditionError { offset, message }
+    }
+}
+
+impl Error for ConditionError {}
+
+impl std::fmt::Display for ConditionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at column {}", self.message, self.offset + 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Name(name) => write!(f, "name '{}'", name),
+            Token::Not => write!(f, "'!'"),
+            Token::And => write!(f, "'&&'"),
+            Token::Or => write!(f, "'||'"),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+        }
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ConditionError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            ' ' | '\t' => {},
+            '!' => tokens.push((Token::Not, offset)),
+            '(' => tokens.push((Token::Open, offset)),
+            ')' => tokens.push((Token::Close, offset)),
+            '&' | '|' => {
+                match chars.next() {
+                    Some((_, next)) if next == c => {},
+                    _ => return Err(ConditionError::new(offset, format!("expected '{}{}'", c, c))),
+                }
+                tokens.push((if c == '&' { Token::And } else { Token::Or }, offset));
+            },
+            c if is_name_char(c) => {
+                let mut name = c.to_string();
+                while let Some((_, next)) = chars.peek() {
+                    if !is_name_char(*next) {
+                        break;
+                    }
+                    name.push(*next);
+                    chars.next();
+                }
+                tokens.push((Token::Name(name), offset));
+            },
+            other => return Err(ConditionError::new(offset, format!("unexpected character '{}'", other))),
+        }
+    }
+
+    Ok(tokens)
+}
+
+// recursive descent over the token list, one method per precedence level
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&(Token, usize)> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    // offset used for errors at the end of the expression
+    fn end(&self) -> usize {
+        self.tokens.last().map(|(_, offset)| offset + 1).unwrap_or(0)
+    }
+
+    fn or(&mut self) -> Result<Condition, ConditionError> {
+        let mut condition = self.and()?;
+        while let Some((Token::Or, _)) = self.peek() {
+            self.next();
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, ConditionError> {
+        let mut condition = self.unary()?;
+        while let Some((Token::And, _)) = self.peek() {
+            self.next();
+            condition = Condition::And(Box::new(condition), Box::new(self.unary()?));
+        }
+        Ok(condition)
+    }
+
+    fn unary(&mut self) -> Result<Condition, ConditionError> {
+        if let Some((Token::Not, _)) = self.peek() {
+            self.next();
+            return Ok(Condition::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Condition, ConditionError> {
+        match self.next() {
+            Some((Token::Open, offset)) => {
+                let condition = self.or()?;
+                match self.next() {
+                    Some((Token::Close, _)) => Ok(condition),
+                    _ => Err(ConditionError::new(offset, "unclosed '('".to_string())),
+                }
+            },
+            Some((Token::Name(name), offset)) => {
+                if let Some((Token::Open, _)) = self.peek() {
+                    self.next();
+                    let argument = match self.next() {
+                        Some((Token::Name(argument), _)) => argument,
+                        _ => return Err(ConditionError::new(offset, format!("{}() expects a step name", name))),
+                    };
+                    match self.next() {
+                        Some((Token::Close, _)) => {},
+                        _ => return Err(ConditionError::new(offset, format!("unclosed '(' after {}", name))),
+                    }
+                    return match name.as_str() {
+                        "ok" => Ok(Condition::Succeeded(argument)),
+                        "failed" => Ok(Condition::Failed(argument)),
+                        "skipped" => Ok(Condition::Skipped(argument)),
+                        "ran" => Ok(Condition::Ran(argument)),
+                        _ => Err(ConditionError::new(offset, format!("unknown function {}(), expected ok, failed, skipped or ran", name))),
+                    };
+                }
+                Ok(Condition::Succeeded(name))
+            },
+            Some((token, offset)) => Err(ConditionError::new(offset, format!("unexpected {}", token))),
+            None => Err(ConditionError::new(self.end(), "expression ended early".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str) -> Box<Condition> {
+        Box::new(Condition::Succeeded(name.to_string()))
+    }
+
+    fn error(source: &str) -> ConditionError {
+        match Condition::parse(source) {
+            Ok(condition) => panic!("{} parsed as {:?}", source, condition),
+            Err(err) => err,
+        }
+    }
+
+    fn results(statuses: &[(&str, StepStatus)]) -> HashMap<String, StepStatus> {
+        statuses.iter().map(|(name, status)| (name.to_string(), *status)).collect()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(Condition::parse("a || b && c").unwrap(),
+            Condition::Or(name("a"), Box::new(Condition::And(name("b"), name("c")))));
+        assert_eq!(Condition::parse("a && b || c").unwrap(),
+            Condition::Or(Box::new(Condition::And(name("a"), name("b"))), name("c")));
+    }
+
+    #[test]
+    fn not_binds_tightest() {
+        assert_eq!(Condition::parse("!a && b").unwrap(),
+            Condition::And(Box::new(Condition::Not(name("a"))), name("b")));
+        assert_eq!(Condition::parse("!!a").unwrap(),
+            Condition::Not(Box::new(Condition::Not(name("a")))));
+    }
+
+    #[test]
+    fn parentheses_group() {
+        assert_eq!(Condition::parse("(a || b) && c").unwrap(),
+            Condition::And(Box::new(Condition::Or(name("a"), name("b"))), name("c")));
+        assert_eq!(Condition::parse("!(a || b)").unwrap(),
+            Condition::Not(Box::new(Condition::Or(name("a"), name("b")))));
+    }
+
+    #[test]
+    fn chains_are_left_associative() {
+        assert_eq!(Condition::parse("a || b || c").unwrap(),
+            Condition::Or(Box::new(Condition::Or(name("a"), name("b"))), name("c")));
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(Condition::parse("ok(a)").unwrap(), Condition::Succeeded("a".to_string()));
+        assert_eq!(Condition::parse("failed(a)").unwrap(), Condition::Failed("a".to_string()));
+        assert_eq!(Condition::parse("skipped( a )").unwrap(), Condition::Skipped("a".to_string()));
+        assert_eq!(Condition::parse("ran(step-1)").unwrap(), Condition::Ran("step-1".to_string()));
+    }
+
+    #[test]
+    fn evaluate_by_status() {
+        let results = results(&[("ok", StepStatus::Succeeded), ("bad", StepStatus::Failed), ("skip", StepStatus::Skipped)]);
+        let evaluate = |source: &str| Condition::parse(source).unwrap().evaluate(&results);
+        assert!(evaluate("ok"));
+        assert!(!evaluate("bad"));
+        assert!(evaluate("failed(bad) && skipped(skip)"));
+        assert!(evaluate("ran(ok) && ran(bad) && !ran(skip)"));
+        // not reached yet: nothing is true of it, so its negation is
+        assert!(!evaluate("ran(later) || failed(later) || skipped(later) || later"));
+        assert!(evaluate("!later"));
+        assert!(evaluate("bad || !ok || skip || ok"));
+    }
+
+    #[test]
+    fn legacy_fields() {
+        let results = results(&[("a", StepStatus::Failed)]);
+        assert_eq!(Condition::from_legacy("", ""), None);
+        assert!(!Condition::from_legacy("a", "").unwrap().evaluate(&results));
+        assert!(Condition::from_legacy("", "a").unwrap().evaluate(&results));
+        assert!(Condition::from_legacy("b", "a").unwrap().evaluate(&results));
+    }
+
+    #[test]
+    fn malformed_input_is_located() {
+        let cases = [
+            ("", 0, "expression ended early"),
+            ("a &&", 3, "expression ended early"),
+            ("a & b", 2, "expected '&&'"),
+            ("a | b", 2, "expected '||'"),
+            ("(a || b", 0, "unclosed '('"),
+            ("a)", 1, "unexpected ')'"),
+            ("a b", 2, "unexpected name 'b'"),
+            ("a && $b", 5, "unexpected character '$'"),
+            ("ok()", 0, "ok() expects a step name"),
+            ("ok(a", 0, "unclosed '(' after ok"),
+            ("done(a)", 0, "unknown function done()"),
+            ("&& a", 0, "unexpected '&&'"),
+        ];
+        for (source, offset, message) in cases.iter() {
+            let err = error(source);
+            assert_eq!(err.offset, *offset, "{}: {}", source, err);
+            assert!(err.message.starts_with(message), "{}: {}", source, err);
+        }
+    }
+}
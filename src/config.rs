@@ -1,33 +1,133 @@
 use serde_derive::Deserialize;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::condition::Condition;
+use crate::validate::{Diagnostic, Severity};
 
 fn default_optional() -> bool { false }
-fn default_logging() -> bool { true }
+fn default_log_level() -> LogLevel { LogLevel::Info }
+fn default_file_log_level() -> LogLevel { LogLevel::Debug }
+fn default_log_format() -> LogFormat { LogFormat::Text }
+fn default_log_settings() -> LogSettings {
+    LogSettings {
+        level: default_log_level(),
+        format: default_log_format(),
+        file: default_empty_string(),
+        file_level: default_file_log_level(),
+    }
+}
+fn default_artifact_settings() -> ArtifactSettings {
+    ArtifactSettings {
+        enabled: default_true(),
+        path: default_empty_string(),
+        screenshot: default_true(),
+        page_source: default_true(),
+        console: default_true(),
+    }
+}
+fn default_notifiers() -> HashMap<String, NotifierSettings> { HashMap::new() }
+fn default_notify_settings() -> NotifySettings {
+    NotifySettings {
+        success: Vec::new(),
+        restarts: Vec::new(),
+        restart_count: default_restart_count(),
+    }
+}
+fn default_channels() -> Vec<String> { Vec::new() }
+fn default_restart_count() -> u32 { 3 }
+fn default_headers() -> HashMap<String, String> { HashMap::new() }
+fn default_smtp_port() -> u16 { 587 }
+fn default_smtp_tls() -> SmtpTls { SmtpTls::StartTls }
+fn default_command_args() -> Vec<String> { Vec::new() }
+fn default_subject() -> String { "[${group}] ${event}".to_string() }
+fn default_control_address() -> String { "127.0.0.1:7878".to_string() }
 fn default_anti_cache() -> bool { false }
+fn default_full_page() -> bool { false }
 fn default_empty_string() -> String { "".into() }
 fn default_wait() -> u64 { 0 }
 fn default_delay() -> u64 { 0 }
+fn default_browser() -> BrowserKind { BrowserKind::Firefox }
+fn default_marionette_port() -> u64 { 2828 }
+fn default_browser_args() -> Vec<String> { Vec::new() }
+fn default_concurrent() -> bool { false }
+fn default_extract_attr() -> String { "text".into() }
+fn default_match_mode() -> MatchMode { MatchMode::Contains }
+fn default_count_op() -> CountOp { CountOp::Equal }
+fn default_true() -> bool { true }
+fn default_url_component() -> UrlComponent { UrlComponent::Full }
+fn default_wait_timeout() -> u64 { 10000 }
+fn default_poll_interval() -> u64 { 250 }
+fn default_script_args() -> Vec<String> { Vec::new() }
+fn default_delay_per_key() -> u64 { 0 }
+fn default_dialog_action() -> DialogAction { DialogAction::Accept }
 
 #[derive(Debug, Deserialize)]
 pub struct Step {
     #[serde(default = "default_empty_string")]
     pub name: String,
+    #[serde(deserialize_with = "deserialize_action")]
     pub action: StepAction,
     #[serde(default = "default_empty_string")]
     pub if_cond: String,
     #[serde(default = "default_empty_string")]
     pub if_not_cond: String,
+    // boolean expression over earlier steps, see condition.rs
+    #[serde(default = "default_empty_string")]
+    pub when: String,
+    // when, or if_cond/if_not_cond, parsed by Config::resolve. None always runs
+    #[serde(skip)]
+    pub condition: Option<Condition>,
     #[serde(default = "default_optional")]
     pub optional: bool,
-    #[serde(default = "default_logging")]
-    pub logging: bool,
+    // level of this step's events, debug hides them from the default console
+    #[serde(default = "default_log_level")]
+    pub log: LogLevel,
+    // replaced by log, logging=false is read as log="debug"
+    pub logging: Option<bool>,
     #[serde(default = "default_wait")]
     pub wait_max: u64,
     #[serde(default = "default_delay")]
     pub delay: u64,
 }
 
+impl Step {
+    pub fn log_level(&self) -> LogLevel {
+        match self.logging {
+            Some(false) => LogLevel::Debug,
+            _ => self.log,
+        }
+    }
+
+    // parse the condition and read the script the step runs. What cannot be
+    // parsed or read is left empty, check_config reports it, a read error is
+    // kept on the step for that
+    fn resolve(&mut self, directory: &Path) {
+        self.condition = if self.when.is_empty() {
+            Condition::from_legacy(&self.if_cond, &self.if_not_cond)
+        } else {
+            Condition::parse(&self.when).ok()
+        };
+        if let StepAction::Script{source, file, code, read_error, ..} = &mut self.action {
+            *code = source.clone();
+            if !file.is_empty() {
+                let path = script_path(directory, file);
+                match fs::read_to_string(&path) {
+                    Ok(text) => *code = text,
+                    Err(err) => *read_error = Some(format!("cannot read {}: {}", path.display(), err)),
+                }
+            }
+        }
+    }
+}
+
+// a Script file, relative to the directory of the config
+pub fn script_path(directory: &Path, file: &str) -> PathBuf {
+    directory.join(file)
+}
+
 #[derive(Debug, Deserialize)]
 pub enum StepAction {
     Navigate{
@@ -36,11 +136,112 @@ pub enum StepAction {
         anti_cache: bool,
     },
     Wait(u64),
-    MatchUrl(String),
-    Screenshot,
+    MatchUrl(UrlMatch),
+    // fail when the price shown by selector is above max. currency, when
+    // set, has to appear in the element text ("$", "EUR", ...)
+    AssertPrice{
+        selector: Selector,
+        max: f64,
+        #[serde(default = "default_empty_string")]
+        currency: String,
+    },
+    // capture the viewport, the whole page, or one element when selector is set.
+    // A plain action = "Screenshot" is the viewport
+    Screenshot{
+        #[serde(default = "default_full_page")]
+        full_page: bool,
+        selector: Option<Selector>,
+        #[serde(default = "default_empty_string")]
+        name: String,
+    },
+    // predicates on the page, a mismatch fails the step with what was
+    // expected and what was seen
+    MatchTitle{
+        value: String,
+        #[serde(default = "default_match_mode")]
+        mode: MatchMode,
+    },
+    MatchText{
+        selector: Selector,
+        value: String,
+        #[serde(default = "default_match_mode")]
+        mode: MatchMode,
+    },
+    MatchAttribute{
+        selector: Selector,
+        attr: String,
+        value: String,
+        #[serde(default = "default_match_mode")]
+        mode: MatchMode,
+    },
+    AssertDisplayed{
+        selector: Selector,
+        #[serde(default = "default_true")]
+        displayed: bool,
+    },
+    AssertEnabled{
+        selector: Selector,
+        #[serde(default = "default_true")]
+        enabled: bool,
+    },
+    AssertCount{
+        selector: Selector,
+        #[serde(default = "default_count_op")]
+        op: CountOp,
+        count: usize,
+    },
+    // poll until the condition holds, fail after timeout milliseconds
+    WaitFor{
+        condition: WaitCondition,
+        #[serde(default = "default_wait_timeout")]
+        timeout: u64,
+        #[serde(default = "default_poll_interval")]
+        poll_interval: u64,
+    },
+    // run JavaScript in the current frame. file is relative to the config.
+    // The result is stored in ${into}, without into a falsy result fails the step
+    Script{
+        #[serde(default = "default_empty_string")]
+        source: String,
+        #[serde(default = "default_empty_string")]
+        file: String,
+        // source, or the contents of file, set by Config::resolve
+        #[serde(skip)]
+        code: String,
+        // why file could not be read, reported by check_config
+        #[serde(skip)]
+        read_error: Option<String>,
+        #[serde(default = "default_script_args")]
+        args: Vec<String>,
+        #[serde(default = "default_empty_string")]
+        into: String,
+        // the script calls arguments[arguments.length - 1](result) when done
+        #[serde(rename = "async", default = "default_optional")]
+        is_async: bool,
+    },
+    // answer the open alert, confirm or prompt. text is typed into a prompt,
+    // the step fails when the dialog does not contain expect_text
+    Dialog{
+        #[serde(default = "default_dialog_action")]
+        action: DialogAction,
+        #[serde(default = "default_empty_string")]
+        text: String,
+        #[serde(default = "default_empty_string")]
+        expect_text: String,
+    },
+    // send message to the notifier named channel, or to every notifier
+    // when channel is empty
+    Notify{
+        message: String,
+        #[serde(default = "default_empty_string")]
+        channel: String,
+    },
+    // leave every frame, or only the innermost one
     TopWindow,
+    ParentFrame,
+    SwitchFrame(FrameTarget),
     Find{
-        selector: String, 
+        selector: Selector,
         action: FindAction,
     },
     Refresh,
@@ -48,6 +249,261 @@ pub enum StepAction {
     Special(SpecialAction),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DialogAction {
+    Accept,
+    Dismiss,
+}
+
+// the option of a SELECT to choose: { value = "2" }, { text = "Large" } or { index = 1 }
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SelectBy {
+    Value(String),
+    Text(String),
+    Index(usize),
+}
+
+// { index = 0 }, { name = "checkout" } or { selector = "#turbo-checkout-iframe" }
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameTarget {
+    Index(usize),
+    // the name or id of the frame element
+    Name(String),
+    Selector(Selector),
+}
+
+#[derive(Debug, Deserialize)]
+pub enum WaitCondition {
+    Present(Selector),
+    Visible(Selector),
+    // visible and not disabled
+    Clickable(Selector),
+    // missing or hidden
+    Gone(Selector),
+    Url(UrlMatch),
+    Title{
+        value: String,
+        #[serde(default = "default_match_mode")]
+        mode: MatchMode,
+    },
+    // document.readyState is "complete"
+    DocumentReady,
+}
+
+// which element a step works on. A plain string is a CSS selector, a table
+// names another strategy: { xpath = "//button", index = 1 }. index picks the
+// Nth match, counting from 0
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "SelectorTable")]
+pub struct Selector {
+    pub by: By,
+    pub value: String,
+    pub index: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum By {
+    Css,
+    XPath,
+    LinkText,
+    PartialLinkText,
+    Id,
+    Name,
+    // the innermost element whose whitespace-normalized text equals the value
+    Text,
+}
+
+impl By {
+    pub fn name(&self) -> &'static str {
+        match self {
+            By::Css => "css",
+            By::XPath => "xpath",
+            By::LinkText => "link_text",
+            By::PartialLinkText => "partial_link_text",
+            By::Id => "id",
+            By::Name => "name",
+            By::Text => "text",
+        }
+    }
+}
+
+impl std::fmt::Display for Selector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.by {
+            By::Css => write!(f, "{}", self.value)?,
+            by => write!(f, "{}={}", by.name(), self.value)?,
+        }
+        if self.index > 0 {
+            write!(f, "[{}]", self.index)?;
+        }
+        Ok(())
+    }
+}
+
+// the forms a selector can be written in
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SelectorTable {
+    Css(String),
+    Table{
+        css: Option<String>,
+        xpath: Option<String>,
+        link_text: Option<String>,
+        partial_link_text: Option<String>,
+        id: Option<String>,
+        name: Option<String>,
+        text: Option<String>,
+        #[serde(default)]
+        index: usize,
+    },
+}
+
+impl std::convert::TryFrom<SelectorTable> for Selector {
+    type Error = String;
+
+    fn try_from(table: SelectorTable) -> Result<Selector, String> {
+        match table {
+            SelectorTable::Css(value) => Ok(Selector { by: By::Css, value, index: 0 }),
+            SelectorTable::Table{css, xpath, link_text, partial_link_text, id, name, text, index} => {
+                let mut given: Vec<(By, String)> = vec![
+                    (By::Css, css), (By::XPath, xpath), (By::LinkText, link_text),
+                    (By::PartialLinkText, partial_link_text), (By::Id, id), (By::Name, name), (By::Text, text),
+                ].into_iter()
+                    .filter_map(|(by, value)| value.map(|value| (by, value)))
+                    .collect();
+                if given.len() != 1 {
+                    return Err("a selector needs exactly one of css, xpath, link_text, partial_link_text, id, name or text".to_string());
+                }
+                let (by, value) = given.remove(0);
+                Ok(Selector { by, value, index })
+            },
+        }
+    }
+}
+
+// how the text of a Match step is compared with what the page shows
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    Contains,
+    #[serde(alias = "equals")]
+    Exact,
+    Regex,
+    // * is any run of characters, ? any one character, the whole text has to match
+    Glob,
+}
+
+// MatchUrl = "amazon.com/gp/cart/" is a contains match on the full url
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum UrlMatch {
+    Contains(String),
+    Pattern{
+        pattern: String,
+        #[serde(default = "default_match_mode")]
+        mode: MatchMode,
+        #[serde(default = "default_url_component")]
+        component: UrlComponent,
+    },
+}
+
+impl UrlMatch {
+    pub fn pattern(&self) -> &str {
+        match self {
+            UrlMatch::Contains(pattern) => pattern,
+            UrlMatch::Pattern{pattern, ..} => pattern,
+        }
+    }
+
+    pub fn mode(&self) -> MatchMode {
+        match self {
+            UrlMatch::Contains(_) => MatchMode::Contains,
+            UrlMatch::Pattern{mode, ..} => *mode,
+        }
+    }
+
+    pub fn component(&self) -> UrlComponent {
+        match self {
+            UrlMatch::Contains(_) => UrlComponent::Full,
+            UrlMatch::Pattern{component, ..} => *component,
+        }
+    }
+}
+
+// the part of the current url MatchUrl looks at
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlComponent {
+    Full,
+    Host,
+    Path,
+    Query,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum CountOp {
+    #[serde(rename = "==")]
+    Equal,
+    #[serde(rename = "!=")]
+    NotEqual,
+    #[serde(rename = "<")]
+    Less,
+    #[serde(rename = "<=")]
+    LessOrEqual,
+    #[serde(rename = ">")]
+    Greater,
+    #[serde(rename = ">=")]
+    GreaterOrEqual,
+}
+
+// Screenshot used to be a unit variant, action = "Screenshot" still reads
+// as a screenshot of the viewport
+fn deserialize_action<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<StepAction, D::Error> {
+    use serde::Deserialize;
+    // read through JSON, toml 0.5's Value cannot hold an externally tagged enum
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(action) if action == "Screenshot" => Ok(StepAction::Screenshot {
+            full_page: default_full_page(),
+            selector: None,
+            name: default_empty_string(),
+        }),
+        value => serde_json::from_value(value).map_err(serde::de::Error::custom),
+    }
+}
+
+impl StepAction {
+    // the variant name, for logs
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StepAction::Navigate{..} => "Navigate",
+            StepAction::Wait(_) => "Wait",
+            StepAction::MatchUrl(_) => "MatchUrl",
+            StepAction::AssertPrice{..} => "AssertPrice",
+            StepAction::Screenshot{..} => "Screenshot",
+            StepAction::MatchTitle{..} => "MatchTitle",
+            StepAction::MatchText{..} => "MatchText",
+            StepAction::MatchAttribute{..} => "MatchAttribute",
+            StepAction::AssertDisplayed{..} => "AssertDisplayed",
+            StepAction::AssertEnabled{..} => "AssertEnabled",
+            StepAction::AssertCount{..} => "AssertCount",
+            StepAction::WaitFor{..} => "WaitFor",
+            StepAction::Script{..} => "Script",
+            StepAction::Dialog{..} => "Dialog",
+            StepAction::Notify{..} => "Notify",
+            StepAction::TopWindow => "TopWindow",
+            StepAction::ParentFrame => "ParentFrame",
+            StepAction::SwitchFrame(_) => "SwitchFrame",
+            StepAction::Find{..} => "Find",
+            StepAction::Refresh => "Refresh",
+            StepAction::End => "End",
+            StepAction::Special(_) => "Special",
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub enum SpecialAction {
     SolveAmazonReCaptcha
@@ -59,24 +515,406 @@ pub enum FindAction {
     Insert(String),
     SwitchFrame,
     None,
+    Clear,
+    // type text one key at a time, waiting delay_per_key milliseconds between keys
+    Type{
+        text: String,
+        #[serde(default = "default_delay_per_key")]
+        delay_per_key: u64,
+    },
+    // "{Enter}", "{Tab}", "{Escape}", ... see keys.rs
+    SendKeys(String),
+    Select(SelectBy),
+    Check,
+    Uncheck,
+    Hover,
+    ScrollIntoView,
+    DoubleClick,
+    RightClick,
+    // store the element's text, or the named attribute, as ${into}
+    Extract{
+        into: String,
+        #[serde(default = "default_extract_attr")]
+        attr: String,
+    },
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Group {
     pub name: String,
+    // overrides for this group's own browser session when concurrent = true.
+    // Ports default to the global ones plus the group's position
+    pub profile: Option<String>,
+    pub profile_directory: Option<String>,
+    pub driver_port: Option<u16>,
+    pub marionette_port: Option<u64>,
+    // answer dialogs no Dialog step expected this way and run the step again.
+    // Without it they fail the step with BrowserOutcome::UnhandledAlert
+    pub on_dialog: Option<DialogAction>,
     pub steps: Vec<Step>,
 }
 
+// ordered from the most to the least verbose
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    // skipped steps are less interesting than the ones that ran
+    pub fn min_debug(self) -> LogLevel {
+        if self > LogLevel::Debug { LogLevel::Debug } else { self }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+// the [log] table
+#[derive(Debug, Deserialize)]
+pub struct LogSettings {
+    // console
+    #[serde(default = "default_log_level")]
+    pub level: LogLevel,
+    #[serde(default = "default_log_format")]
+    pub format: LogFormat,
+    // JSON lines are appended here when set
+    #[serde(default = "default_empty_string")]
+    pub file: String,
+    #[serde(default = "default_file_log_level")]
+    pub file_level: LogLevel,
+}
+
+// the [artifacts] table, what is saved when a step that is not optional fails.
+// Each failure gets its own directory under path
+#[derive(Debug, Clone, Deserialize)]
+pub struct ArtifactSettings {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    // empty means a failures directory inside screenshot_path
+    #[serde(default = "default_empty_string")]
+    pub path: String,
+    #[serde(default = "default_true")]
+    pub screenshot: bool,
+    // HTML of the frame the step failed in
+    #[serde(default = "default_true")]
+    pub page_source: bool,
+    #[serde(default = "default_true")]
+    pub console: bool,
+}
+
+// one [notifiers.<channel>] table. ${event}, ${group} and ${message} are
+// replaced in subject and args
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotifierSettings {
+    // POST the notification as JSON
+    Webhook{
+        url: String,
+        #[serde(default = "default_headers")]
+        headers: HashMap<String, String>,
+    },
+    Smtp{
+        server: String,
+        #[serde(default = "default_smtp_port")]
+        port: u16,
+        #[serde(default = "default_smtp_tls")]
+        tls: SmtpTls,
+        // no AUTH when empty
+        #[serde(default = "default_empty_string")]
+        username: String,
+        #[serde(default = "default_empty_string")]
+        password: String,
+        from: String,
+        to: Vec<String>,
+        #[serde(default = "default_subject")]
+        subject: String,
+    },
+    // run a program, it also gets NOTIFY_EVENT, NOTIFY_GROUP and NOTIFY_MESSAGE
+    Command{
+        program: String,
+        #[serde(default = "default_command_args")]
+        args: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    // plain text, only for a relay on the same machine
+    None,
+    #[serde(rename = "starttls")]
+    StartTls,
+    // TLS from the start, usually port 465
+    Implicit,
+}
+
+// the [notify] table, channels told about events the runner sees
+#[derive(Debug, Deserialize)]
+pub struct NotifySettings {
+    // a group ended OK
+    #[serde(default = "default_channels")]
+    pub success: Vec<String>,
+    // the browser restarted restart_count times in a row
+    #[serde(default = "default_channels")]
+    pub restarts: Vec<String>,
+    #[serde(default = "default_restart_count")]
+    pub restart_count: u32,
+}
+
+// the [control] table, starts the HTTP control API
+#[derive(Debug, Clone, Deserialize)]
+pub struct ControlSettings {
+    // has to be a loopback address
+    #[serde(default = "default_control_address")]
+    pub address: String,
+    // when set, requests need "Authorization: Bearer <token>"
+    #[serde(default = "default_empty_string")]
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BrowserKind {
+    Firefox,
+    #[serde(alias = "chromium")]
+    Chrome,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
+    #[serde(default = "default_browser")]
+    pub browser: BrowserKind,
+    // empty means geckodriver/chromedriver in the working directory or on the PATH
+    #[serde(default = "default_empty_string")]
+    pub driver_path: String,
+    pub driver_port: Option<u16>,
+    // Firefox profile path, or the Chrome user-data-dir
     pub profile: String,
+    // Chrome only, the profile inside user-data-dir ("Default", "Profile 1", ...)
+    #[serde(default = "default_empty_string")]
+    pub profile_directory: String,
+    // extra command line arguments for the browser
+    #[serde(default = "default_browser_args")]
+    pub browser_args: Vec<String>,
     pub screenshot_path: String,
+    // Firefox only
+    #[serde(default = "default_marionette_port")]
     pub marionette_port: u64,
     pub timeout: u64,
+    // run every group in its own browser session and task
+    #[serde(default = "default_concurrent")]
+    pub concurrent: bool,
+    #[serde(default = "default_log_settings")]
+    pub log: LogSettings,
+    // run journal, every pass and step is appended here as a JSON line.
+    // Empty disables it
+    #[serde(default = "default_empty_string")]
+    pub journal: String,
+    #[serde(default = "default_artifact_settings")]
+    pub artifacts: ArtifactSettings,
+    #[serde(default = "default_notifiers")]
+    pub notifiers: HashMap<String, NotifierSettings>,
+    #[serde(default = "default_notify_settings")]
+    pub notify: NotifySettings,
+    // no control API without the table
+    pub control: Option<ControlSettings>,
     pub groups: Vec<Group>,
 }
 
-pub fn load_config(path: &str) -> Result<Config, Box<dyn Error>> {
-    let file_string = fs::read_to_string(path)?;
-    Ok(toml::from_str(&file_string)?)
-}
\ No newline at end of file
+// everything needed to launch a driver and open a session on it
+#[derive(Debug, Clone)]
+pub struct DriverSettings {
+    pub browser: BrowserKind,
+    pub driver_path: String,
+    pub driver_port: u16,
+    pub profile: String,
+    pub profile_directory: String,
+    pub browser_args: Vec<String>,
+    pub marionette_port: u64,
+}
+
+impl Config {
+    // fill in what the steps need besides their settings, path is the config file
+    pub fn resolve(&mut self, path: &str) {
+        let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        for group in self.groups.iter_mut() {
+            for step in group.steps.iter_mut() {
+                step.resolve(directory);
+            }
+        }
+    }
+
+    pub fn driver_settings(&self) -> DriverSettings {
+        let driver_path = if self.driver_path.is_empty() {
+            match self.browser {
+                BrowserKind::Firefox => crate::process::DEFAULT_GECKODRIVER.to_string(),
+                BrowserKind::Chrome => crate::process::DEFAULT_CHROMEDRIVER.to_string(),
+            }
+        } else {
+            self.driver_path.clone()
+        };
+        let driver_port = self.driver_port.unwrap_or(match self.browser {
+            BrowserKind::Firefox => 4444,
+            BrowserKind::Chrome => 9515,
+        });
+
+        DriverSettings {
+            browser: self.browser,
+            driver_path,
+            driver_port,
+            profile: self.profile.clone(),
+            profile_directory: self.profile_directory.clone(),
+            browser_args: self.browser_args.clone(),
+            marionette_port: self.marionette_port,
+        }
+    }
+
+    // indexes of the groups named with --group, in config order, or of every group.
+    // With concurrent = true a group's position in this list is its slot
+    pub fn select_groups(&self, names: &[String]) -> Result<Vec<usize>, String> {
+        for name in names {
+            if !self.groups.iter().any(|group| &group.name == name) {
+                return Err(format!("no group named [{}] in the config", name));
+            }
+        }
+        Ok(self.groups.iter().enumerate()
+            .filter(|(_, group)| names.is_empty() || names.contains(&group.name))
+            .map(|(index, _)| index)
+            .collect())
+    }
+
+    // settings for a group running in its own session, slot is its position
+    // among the groups being run so every session gets distinct ports
+    pub fn group_driver_settings(&self, group: &Group, slot: usize) -> Result<DriverSettings, String> {
+        let mut settings = self.driver_settings();
+        settings.driver_port = match group.driver_port {
+            Some(port) => port,
+            None => std::convert::TryFrom::try_from(slot).ok()
+                .and_then(|slot| settings.driver_port.checked_add(slot))
+                .ok_or_else(|| format!("driver port {} + slot {} is past port 65535", settings.driver_port, slot))?,
+        };
+        settings.marionette_port = match group.marionette_port {
+            Some(port) => port,
+            None => settings.marionette_port.checked_add(slot as u64)
+                .filter(|port| *port <= u64::from(u16::MAX))
+                .ok_or_else(|| format!("marionette port {} + slot {} is past port 65535", settings.marionette_port, slot))?,
+        };
+        if let Some(profile) = &group.profile {
+            settings.profile = profile.clone();
+        }
+        if let Some(profile_directory) = &group.profile_directory {
+            settings.profile_directory = profile_directory.clone();
+        }
+        Ok(settings)
+    }
+}
+
+// log what check_config found, an Err when any of it is an error
+pub fn report_diagnostics(path: &str, diagnostics: &[Diagnostic]) -> Result<(), Box<dyn Error>> {
+    for diagnostic in diagnostics {
+        match diagnostic.severity {
+            Severity::Error => tracing::error!("{}", diagnostic),
+            Severity::Warning => tracing::warn!("{}", diagnostic),
+        }
+    }
+    let errors = diagnostics.iter().filter(|diagnostic| diagnostic.severity == Severity::Error).count();
+    if errors > 0 {
+        return Err(format!("{} has {} error(s)", path, errors).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(action: &str) -> StepAction {
+        let step: Step = toml::from_str(&format!("action = {}", action)).unwrap();
+        step.action
+    }
+
+    #[test]
+    fn plain_screenshot_is_the_viewport() {
+        match action("\"Screenshot\"") {
+            StepAction::Screenshot{full_page, selector, name} => {
+                assert!(!full_page);
+                assert!(selector.is_none());
+                assert!(name.is_empty());
+            },
+            other => panic!("expected Screenshot, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn screenshot_table_keeps_its_settings() {
+        match action("{ Screenshot = { full_page = true, selector = { id = \"price\" }, name = \"price\" } }") {
+            StepAction::Screenshot{full_page, selector, name} => {
+                assert!(full_page);
+                assert_eq!(selector.map(|selector| selector.to_string()), Some("id=price".to_string()));
+                assert_eq!(name, "price");
+            },
+            other => panic!("expected Screenshot, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn other_actions_are_unchanged() {
+        assert!(matches!(action("\"End\""), StepAction::End));
+        assert!(matches!(action("{ Wait = 5 }"), StepAction::Wait(5)));
+        let err = toml::from_str::<Step>("action = { Navigate = { urll = \"x\" } }").unwrap_err();
+        assert!(err.to_string().contains("missing field `url`"), "{}", err);
+    }
+
+    fn resolved(step: &str, directory: &Path) -> Step {
+        let mut step: Step = toml::from_str(step).unwrap();
+        step.resolve(directory);
+        step
+    }
+
+    fn code(step: &Step) -> &str {
+        match &step.action {
+            StepAction::Script{code, ..} => code,
+            other => panic!("expected Script, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn resolve_reads_the_script_file_next_to_the_config() {
+        let directory = std::env::temp_dir().join(format!("scraper-resolve-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("check.js"), "return true;").unwrap();
+
+        let step = resolved("action = { Script = { file = \"check.js\" } }", &directory);
+        assert_eq!(code(&step), "return true;");
+        let step = resolved("action = { Script = { source = \"return 1;\" } }", &directory);
+        assert_eq!(code(&step), "return 1;");
+        let step = resolved("action = { Script = { file = \"missing.js\" } }", &directory);
+        assert_eq!(code(&step), "");
+        match &step.action {
+            StepAction::Script{read_error: Some(err), ..} => assert!(err.starts_with("cannot read "), "{}", err),
+            other => panic!("expected a read error, got {:?}", other),
+        }
+
+        fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn resolve_parses_the_condition() {
+        let directory = Path::new("");
+        assert!(resolved("action = \"End\"", directory).condition.is_none());
+        assert!(resolved("action = \"End\"\nwhen = \"a && !b\"", directory).condition.is_some());
+        assert!(resolved("action = \"End\"\nif_cond = \"a\"", directory).condition.is_some());
+        // check_config reports it
+        assert!(resolved("action = \"End\"\nwhen = \"a &&\"", directory).condition.is_none());
+    }
+}
@@ -0,0 +1,398 @@
+use serde_derive::Serialize;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::browser::BrowserOutcome;
+use crate::cancel::CancelToken;
+use crate::config::Config;
+use crate::journal;
+use crate::notify::Notifiers;
+use crate::validate::{self, Severity};
+
+// what the runner does with a group before its next pass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Next {
+    Run,
+    // paused, ask again later
+    Wait,
+    // stopped, or removed from the config by a reload
+    Stop,
+}
+
+// a group as the control API shows it
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupStatus {
+    pub name: String,
+    // "waiting", "running", "paused", "stopping", "stopped" or "done"
+    pub state: String,
+    pub iteration: u64,
+    // the step running now, and since when (ms since the epoch)
+    pub step: Option<String>,
+    pub step_started: Option<u64>,
+    // "ok", "failed" or "cancelled", for the last pass that ended
+    pub last_outcome: Option<String>,
+    pub last_kind: Option<String>,
+    pub last_error: Option<String>,
+    pub last_ended: Option<u64>,
+    pub passes_ok: u64,
+    pub passes_failed: u64,
+    pub restarts: u64,
+    // single passes asked for with run while paused
+    pub queued_runs: u32,
+}
+
+#[derive(Serialize)]
+pub struct RunStatus {
+    pub config: String,
+    pub started: u64,
+    pub reloaded: Option<u64>,
+    pub groups: Vec<GroupStatus>,
+}
+
+#[derive(Serialize)]
+pub struct ReloadReport {
+    pub warnings: Vec<String>,
+    // groups only in the new config. A run keeps the groups it started with,
+    // these only run after a restart of the bot
+    pub requires_restart: Vec<String>,
+    // groups gone from the new config, they stop after their current pass
+    pub removed: Vec<String>,
+}
+
+// a control command that cannot be carried out
+#[derive(Debug)]
+pub enum ControlError {
+    UnknownGroup(String),
+    // the group is not in a state the command applies to
+    Conflict(String),
+    InvalidConfig(String),
+}
+
+impl std::fmt::Display for ControlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ControlError::UnknownGroup(name) => write!(f, "no running group named [{}]", name),
+            ControlError::Conflict(issue) => write!(f, "{}", issue),
+            ControlError::InvalidConfig(issue) => write!(f, "config not reloaded: {}", issue),
+        }
+    }
+}
+
+struct GroupControl {
+    status: GroupStatus,
+    running: bool,
+    paused: bool,
+    stopped: bool,
+    done: bool,
+    // cancelled by stop, set once the runner registers the group
+    cancel: Option<CancelToken>,
+}
+
+impl GroupControl {
+    fn status(&self) -> GroupStatus {
+        let state = if self.done {
+            "done"
+        } else if self.stopped && self.running {
+            "stopping"
+        } else if self.stopped {
+            "stopped"
+        } else if self.running {
+            "running"
+        } else if self.paused {
+            "paused"
+        } else {
+            "waiting"
+        };
+        GroupStatus { state: state.to_string(), ..self.status.clone() }
+    }
+}
+
+struct State {
+    path: String,
+    config: Arc<Config>,
+    notifiers: Notifiers,
+    groups: Vec<GroupControl>,
+    started: u64,
+    reloaded: Option<u64>,
+    stop_requested: bool,
+}
+
+// shared between the runner, which reports every pass and step and asks
+// before each pass whether a group may run, and the control API, which reads
+// the status and pauses, resumes, stops and reloads. The lock is never held
+// across an await
+#[derive(Clone)]
+pub struct Control {
+    state: Arc<Mutex<State>>,
+    // cancelled by a stop of the whole run
+    cancel: CancelToken,
+}
+
+impl Control {
+    // groups are the names of the groups this run goes through
+    pub fn new(path: &str, config: Arc<Config>, notifiers: Notifiers, cancel: CancelToken, groups: &[String]) -> Control {
+        let groups = groups.iter()
+            .map(|name| GroupControl {
+                status: GroupStatus {
+                    name: name.clone(),
+                    state: String::new(),
+                    iteration: 0,
+                    step: None,
+                    step_started: None,
+                    last_outcome: None,
+                    last_kind: None,
+                    last_error: None,
+                    last_ended: None,
+                    passes_ok: 0,
+                    passes_failed: 0,
+                    restarts: 0,
+                    queued_runs: 0,
+                },
+                running: false,
+                paused: false,
+                stopped: false,
+                done: false,
+                cancel: None,
+            })
+            .collect();
+        let state = State {
+            path: path.to_string(),
+            config,
+            notifiers,
+            groups,
+            started: journal::now(),
+            reloaded: None,
+            stop_requested: false,
+        };
+        Control { state: Arc::new(Mutex::new(state)), cancel }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    // apply a change to the named group, unknown names are ignored so the
+    // runner never fails over a status update
+    fn update<F: FnOnce(&mut GroupControl)>(&self, name: &str, change: F) {
+        if let Some(group) = self.lock().groups.iter_mut().find(|group| group.status.name == name) {
+            change(group);
+        }
+    }
+
+    // a command from the API on the named group
+    fn command<F>(&self, name: &str, command: F) -> Result<GroupStatus, ControlError>
+    where F: FnOnce(&mut GroupControl) -> Result<(), ControlError> {
+        let mut state = self.lock();
+        let group = state.groups.iter_mut().find(|group| group.status.name == name)
+            .ok_or_else(|| ControlError::UnknownGroup(name.to_string()))?;
+        command(group)?;
+        Ok(group.status())
+    }
+
+    // the config as of the last reload
+    pub fn config(&self) -> Arc<Config> {
+        self.lock().config.clone()
+    }
+
+    pub fn notifiers(&self) -> Notifiers {
+        self.lock().notifiers.clone()
+    }
+
+    // the token the group's passes run with: a child of parent that stop cancels
+    pub fn register(&self, name: &str, parent: &CancelToken) -> CancelToken {
+        let token = parent.child();
+        self.update(name, |group| {
+            if group.stopped {
+                token.cancel();
+            }
+            group.cancel = Some(token.clone());
+        });
+        token
+    }
+
+    pub fn next_pass(&self, name: &str) -> Next {
+        let mut state = self.lock();
+        let group = match state.groups.iter_mut().find(|group| group.status.name == name) {
+            Some(group) => group,
+            None => return Next::Stop,
+        };
+        if group.stopped {
+            Next::Stop
+        } else if !group.paused {
+            Next::Run
+        } else if group.status.queued_runs > 0 {
+            group.status.queued_runs -= 1;
+            Next::Run
+        } else {
+            Next::Wait
+        }
+    }
+
+    pub fn pass_started(&self, name: &str, iteration: u64) {
+        self.update(name, |group| {
+            group.running = true;
+            group.status.iteration = iteration;
+        });
+    }
+
+    pub fn step_started(&self, name: &str, step: &str) {
+        self.update(name, |group| {
+            group.status.step = Some(step.to_string());
+            group.status.step_started = Some(journal::now());
+        });
+    }
+
+    pub fn pass_ended(&self, name: &str, result: &Result<(), BrowserOutcome>) {
+        self.update(name, |group| {
+            group.running = false;
+            group.status.step = None;
+            group.status.step_started = None;
+            group.status.last_ended = Some(journal::now());
+            let (outcome, kind, error) = match result {
+                Ok(_) => ("ok", None, None),
+                Err(BrowserOutcome::Cancelled) => ("cancelled", None, None),
+                Err(err) => ("failed", Some(err.kind().to_string()), Some(err.to_string())),
+            };
+            match outcome {
+                "ok" => group.status.passes_ok += 1,
+                "failed" => group.status.passes_failed += 1,
+                _ => {},
+            }
+            group.status.last_outcome = Some(outcome.to_string());
+            group.status.last_kind = kind;
+            group.status.last_error = error;
+        });
+    }
+
+    pub fn restarted(&self, name: &str) {
+        self.update(name, |group| group.status.restarts += 1);
+    }
+
+    // the runner is done with the group
+    pub fn finished(&self, name: &str) {
+        self.update(name, |group| {
+            group.running = false;
+            group.done = true;
+        });
+    }
+
+    // takes effect before the group's next pass
+    pub fn pause(&self, name: &str) -> Result<GroupStatus, ControlError> {
+        self.command(name, |group| {
+            if group.done || group.stopped {
+                return Err(ControlError::Conflict(format!("[{}] is stopped", group.status.name)));
+            }
+            group.paused = true;
+            Ok(())
+        })
+    }
+
+    pub fn resume(&self, name: &str) -> Result<GroupStatus, ControlError> {
+        self.command(name, |group| {
+            if group.done || group.stopped {
+                return Err(ControlError::Conflict(format!("[{}] is stopped", group.status.name)));
+            }
+            group.paused = false;
+            group.status.queued_runs = 0;
+            Ok(())
+        })
+    }
+
+    // cancels the current pass right away, the group does not run again
+    pub fn stop(&self, name: &str) -> Result<GroupStatus, ControlError> {
+        self.command(name, |group| {
+            if group.done {
+                return Err(ControlError::Conflict(format!("[{}] is done", group.status.name)));
+            }
+            group.stopped = true;
+            if let Some(cancel) = &group.cancel {
+                cancel.cancel();
+            }
+            Ok(())
+        })
+    }
+
+    // one pass of a paused group, which is paused again after it
+    pub fn run(&self, name: &str) -> Result<GroupStatus, ControlError> {
+        self.command(name, |group| {
+            if group.done || group.stopped {
+                return Err(ControlError::Conflict(format!("[{}] is stopped", group.status.name)));
+            }
+            if !group.paused {
+                return Err(ControlError::Conflict(format!("[{}] is not paused, it runs already", group.status.name)));
+            }
+            group.status.queued_runs += 1;
+            Ok(())
+        })
+    }
+
+    // stop every group, like a signal but the bot exits normally
+    pub fn stop_all(&self) {
+        self.lock().stop_requested = true;
+        self.cancel.cancel();
+    }
+
+    pub fn stop_requested(&self) -> bool {
+        self.lock().stop_requested
+    }
+
+    pub fn status(&self) -> RunStatus {
+        let state = self.lock();
+        RunStatus {
+            config: state.path.clone(),
+            started: state.started,
+            reloaded: state.reloaded,
+            groups: state.groups.iter().map(|group| group.status()).collect(),
+        }
+    }
+
+    pub fn group(&self, name: &str) -> Result<GroupStatus, ControlError> {
+        self.command(name, |_| Ok(()))
+    }
+
+    // read the config file again. Passes started after this use its groups,
+    // notifiers and [notify] table. Browser settings, concurrent and the
+    // groups the run goes through only change with a restart of the bot
+    pub fn reload(&self) -> Result<ReloadReport, ControlError> {
+        // the groups of the run keep their slots, check the new config with them
+        let (path, names) = {
+            let state = self.lock();
+            (state.path.clone(), state.groups.iter().map(|group| group.status.name.clone()).collect::<Vec<String>>())
+        };
+        let (config, diagnostics) = validate::check_config(&path, &names)
+            .map_err(|err| ControlError::InvalidConfig(err.to_string()))?;
+        let errors: Vec<String> = diagnostics.iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        if !errors.is_empty() {
+            return Err(ControlError::InvalidConfig(errors.join("; ")));
+        }
+        let notifiers = Notifiers::new(&config.notifiers)
+            .map_err(|err| ControlError::InvalidConfig(err.to_string()))?;
+        let warnings = diagnostics.iter().map(|diagnostic| diagnostic.to_string()).collect();
+
+        let mut state = self.lock();
+        let requires_restart: Vec<String> = config.groups.iter()
+            .filter(|group| !state.groups.iter().any(|known| known.status.name == group.name))
+            .map(|group| group.name.clone())
+            .collect();
+        for name in &requires_restart {
+            tracing::warn!(group = %name, "new group in the reloaded config, it only runs after a restart");
+        }
+        let mut removed = Vec::new();
+        for group in state.groups.iter_mut() {
+            if !group.done && !config.groups.iter().any(|new| new.name == group.status.name) {
+                group.stopped = true;
+                removed.push(group.status.name.clone());
+            }
+        }
+        state.config = Arc::new(config);
+        state.notifiers = notifiers;
+        state.reloaded = Some(journal::now());
+        tracing::info!(path = %path, "config reloaded");
+        Ok(ReloadReport { warnings, requires_restart, removed })
+    }
+}
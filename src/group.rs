@@ -1,49 +1,612 @@
 use crate::config::*;
+use crate::artifacts::{Artifacts, Failure};
+use crate::backend::BrowserBackend;
 use crate::browser::*;
+use crate::cancel::CancelToken;
+use crate::condition::StepStatus;
 use crate::step::*;
-use std::collections::{HashSet};
+use crate::journal::{Journal, Record, StepRecord};
+use crate::control::Control;
+use crate::notify::Notifiers;
+use crate::logging::event_at;
+use crate::vars::Variables;
+use std::collections::{HashMap};
+use std::time::Instant;
 
-// handle if_not_cond, if_cond, optional, and logging inside sites.toml
-pub async fn process_group(group: &Group, browser: &mut Browser) -> Result<(), BrowserOutcome> {
+// one pass of a group and everything it reports to
+pub struct Pass<'a> {
+    pub iteration: u64,
+    // cancelled by a signal, another group ending OK, or a stop of this group
+    pub cancel: &'a CancelToken,
+    pub journal: &'a Journal,
+    pub artifacts: &'a Artifacts,
+    pub notifiers: &'a Notifiers,
+    pub control: &'a Control,
+}
+
+// handle when/if_not_cond/if_cond, optional, and log inside sites.toml
+// a cancelled token ends the group with BrowserOutcome::Cancelled.
+// The pass and every step in it are recorded in the journal, a failure
+// that ends the pass also saves its artifacts
+pub async fn process_group(group: &Group, browser: &mut dyn BrowserBackend, pass: &Pass<'_>) -> Result<(), BrowserOutcome> {
+    let (journal, iteration) = (pass.journal, pass.iteration);
+    journal.write(Record::GroupStarted { group: group.name.clone(), iteration });
+    let start_time = Instant::now();
+    let result = process_steps(group, browser, pass).await;
+
+    let (outcome, kind, error) = match &result {
+        Ok(_) => ("ok", None, None),
+        Err(BrowserOutcome::Cancelled) => ("cancelled", None, None),
+        Err(err) => ("failed", Some(err.kind().to_string()), Some(err.to_string())),
+    };
+    journal.write(Record::GroupEnded {
+        group: group.name.clone(),
+        iteration,
+        outcome: outcome.to_string(),
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        kind,
+        error,
+    });
+    result
+}
 
-    let mut success: HashSet<String> = HashSet::new();
-    let mut failed: HashSet<String> = HashSet::new();
+async fn process_steps(group: &Group, browser: &mut dyn BrowserBackend, pass: &Pass<'_>) -> Result<(), BrowserOutcome> {
+    let (journal, iteration) = (pass.journal, pass.iteration);
+    let mut results: HashMap<String, StepStatus> = HashMap::new();
+    let mut context = StepContext {
+        group: &group.name,
+        cancel: pass.cancel,
+        notifiers: pass.notifiers,
+        vars: Variables::new(),
+        on_dialog: group.on_dialog,
+        attempt: 0,
+        retries: Vec::new(),
+        artifacts: Vec::new(),
+    };
 
     for step in &group.steps {
-        if (step.if_cond.is_empty() && step.if_not_cond.is_empty())
-        || (!step.if_cond.is_empty() && success.contains(&step.if_cond))
-        || (!step.if_not_cond.is_empty() && failed.contains(&step.if_not_cond)) {
-
-            match process_step(step, browser).await {
-                Err(err) => {
-                    log(format!("Step [{}:{}] failed", group.name,step.name ),&step.logging);
-                    if !step.optional {
-                        if step.logging {
-                            if let Err(err) = browser.screenshot().await {
-                                log(format!("Step [{}:{}] {}", group.name,step.name, err),&step.logging);
-                            }
+        let should_run = match &step.condition {
+            Some(condition) => condition.evaluate(&results),
+            None => true,
+        };
+
+        if !should_run {
+            let reason = skip_reason(step, &results);
+            event_at!(step.log_level().min_debug(), group = %group.name, step = %step.name, action = step.action.kind(), outcome = "skipped", reason = %reason, "step skipped");
+            journal.write(Record::Step(StepRecord {
+                group: group.name.clone(),
+                iteration,
+                step: step.name.clone(),
+                action: step.action.kind().to_string(),
+                outcome: "skipped".to_string(),
+                reason: Some(reason),
+                optional: step.optional,
+                attempts: 0,
+                retries: Vec::new(),
+                duration_ms: 0,
+                kind: None,
+                error: None,
+                url: String::new(),
+                frames: Vec::new(),
+                artifacts: Vec::new(),
+            }));
+            if !step.name.is_empty() {
+                results.insert(step.name.clone(), StepStatus::Skipped);
+            }
+            continue;
+        }
+
+        pass.control.step_started(&group.name, &step.name);
+        let start_time = Instant::now();
+        let result = process_step(step, browser, &mut context).await;
+        if let Err(BrowserOutcome::Cancelled) = result {
+            return Err(BrowserOutcome::Cancelled);
+        }
+
+        let duration_ms = start_time.elapsed().as_millis() as u64;
+        // an open dialog fails this, the url is only for the log
+        let url = browser.current_url().await.unwrap_or_default();
+        let frames = browser.frames();
+        let level = step.log_level();
+        let mut record = StepRecord {
+            group: group.name.clone(),
+            iteration,
+            step: step.name.clone(),
+            action: step.action.kind().to_string(),
+            outcome: String::new(),
+            reason: None,
+            optional: step.optional,
+            attempts: context.attempt,
+            retries: std::mem::take(&mut context.retries),
+            duration_ms,
+            kind: None,
+            error: None,
+            url: url.clone(),
+            frames: frames.clone(),
+            artifacts: std::mem::take(&mut context.artifacts),
+        };
+        let frames = frames.join(" > ");
+
+        match result {
+            Err(err) => {
+                event_at!(level, group = %group.name, step = %step.name, action = step.action.kind(),
+                    attempt = context.attempt, duration_ms, url = %url, frames = %frames,
+                    optional = step.optional, outcome = "failed", error = %err, "step failed");
+                record.outcome = "failed".to_string();
+                record.kind = Some(err.kind().to_string());
+                record.error = Some(err.to_string());
+                if !step.optional {
+                    if level >= LogLevel::Info {
+                        let failure = Failure {
+                            group: &group.name,
+                            iteration,
+                            step,
+                            error: &err,
+                            attempts: record.attempts,
+                            retries: &record.retries,
+                            url: &record.url,
+                            frames: &record.frames,
+                        };
+                        match pass.artifacts.save(&failure, browser).await {
+                            Ok(Some(path)) => {
+                                event_at!(level, group = %group.name, step = %step.name, path = %path.display(), "failure artifacts saved");
+                                record.artifacts.push(path.display().to_string());
+                            },
+                            Ok(None) => {},
+                            Err(err) => event_at!(level, group = %group.name, step = %step.name, error = %err, "failure artifacts not saved"),
                         }
-                        return Err(err);
-                    }
-                    if !step.name.is_empty() {
-                        failed.insert(step.name.clone());
                     }
-                },
-                Ok(_) => {
-                    log(format!("Step [{}:{}] success", group.name,step.name ),&step.logging);
-                    if !step.name.is_empty() {
-                        success.insert(step.name.clone());
-                    }
-                },
-            }
+                    journal.write(Record::Step(record));
+                    return Err(err);
+                }
+                journal.write(Record::Step(record));
+                if !step.name.is_empty() {
+                    results.insert(step.name.clone(), StepStatus::Failed);
+                }
+            },
+            Ok(_) => {
+                event_at!(level, group = %group.name, step = %step.name, action = step.action.kind(),
+                    attempt = context.attempt, duration_ms, url = %url, frames = %frames,
+                    outcome = "succeeded", "step succeeded");
+                record.outcome = "succeeded".to_string();
+                journal.write(Record::Step(record));
+                if !step.name.is_empty() {
+                    results.insert(step.name.clone(), StepStatus::Succeeded);
+                }
+            },
         }
     }
     
     Ok(())
 }
 
-pub fn log(message: String, log: &bool) {
-    if *log {
-        println!("{}",message);
+// the condition as written in sites.toml and what the steps it names did
+fn skip_reason(step: &Step, results: &HashMap<String, StepStatus>) -> String {
+    let source = if !step.when.is_empty() {
+        format!("when \"{}\"", step.when)
+    } else {
+        let mut legacy = Vec::new();
+        if !step.if_cond.is_empty() {
+            legacy.push(format!("if_cond \"{}\"", step.if_cond));
+        }
+        if !step.if_not_cond.is_empty() {
+            legacy.push(format!("if_not_cond \"{}\"", step.if_not_cond));
+        }
+        legacy.join(" or ")
+    };
+
+    let mut names: Vec<&str> = Vec::new();
+    if let Some(condition) = &step.condition {
+        for name in condition.names() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
     }
-}
\ No newline at end of file
+    let statuses: Vec<String> = names.iter()
+        .map(|name| {
+            let status = match results.get(*name) {
+                Some(StepStatus::Succeeded) => "succeeded",
+                Some(StepStatus::Failed) => "failed",
+                Some(StepStatus::Skipped) => "skipped",
+                None => "not reached",
+            };
+            format!("{} {}", name, status)
+        })
+        .collect();
+    format!("{} is false ({})", source, statuses.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockBrowser, MockElement, MockPage};
+    use crate::validate::{self, Severity};
+    use std::sync::Arc;
+
+    const SHOP: &str = "https://shop.test/item";
+
+    // a config with one group "test" made of the given steps, conditions compiled
+    fn config(steps: &str) -> Config {
+        group_config("", steps)
+    }
+
+    // the same with more fields on the group, "on_dialog = \"accept\", "
+    fn group_config(fields: &str, steps: &str) -> Config {
+        let source = format!("profile = \"\"\nscreenshot_path = \"\"\ntimeout = 1000\ngroups = [\n{{ name = \"test\", {}steps = [\n{}\n]}},\n]\n", fields, steps);
+        let (config, diagnostics) = validate::check_source("sites.toml", &source, &[]).unwrap();
+        let errors: Vec<String> = diagnostics.iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        assert!(errors.is_empty(), "{:?}", errors);
+        config
+    }
+
+    fn browser() -> MockBrowser {
+        MockBrowser::new(1).page(SHOP, MockPage::new()
+            .element("#buy", MockElement::new())
+            .element("#price", MockElement::new().text("$499.99")))
+    }
+
+    async fn run(config: Config, browser: &mut MockBrowser) -> Result<(), BrowserOutcome> {
+        let config = Arc::new(config);
+        let cancel = CancelToken::new();
+        let names = vec!["test".to_string()];
+        let control = Control::new("sites.toml", config.clone(), Notifiers::dry_run(), cancel.clone(), &names);
+        let pass = Pass {
+            iteration: 1,
+            cancel: &cancel,
+            journal: &Journal::disabled(),
+            artifacts: &Artifacts::disabled(),
+            notifiers: &Notifiers::dry_run(),
+            control: &control,
+        };
+        process_group(&config.groups[0], browser, &pass).await
+    }
+
+    fn ran(browser: &MockBrowser, call: &str) -> usize {
+        browser.history.iter().filter(|entry| entry.as_str() == call).count()
+    }
+
+    #[tokio::test]
+    async fn if_cond_and_if_not_cond_skip_on_the_named_step() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { name = "missing", action = { Find = { selector = "#missing", action = "Click" } }, optional = true },
+            { name = "after_missing", action = { Find = { selector = "#buy", action = "Click" } }, if_cond = "missing" },
+            { name = "instead", action = { Find = { selector = "#price", action = "Click" } }, if_not_cond = "missing" },
+            { action = { Find = { selector = "#buy", action = { Insert = "1" } } }, if_cond = "instead" },
+        "##);
+        let mut browser = browser();
+        run(config, &mut browser).await.unwrap();
+        assert_eq!(ran(&browser, "click #buy"), 0);
+        assert_eq!(ran(&browser, "click #price"), 1);
+        assert_eq!(browser.value("#buy"), Some("1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn when_skips_are_chained() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { name = "a", action = { Find = { selector = "#missing", action = "Click" } }, optional = true },
+            { name = "b", action = { Find = { selector = "#buy", action = "Click" } }, when = "a" },
+            { name = "c", action = { Find = { selector = "#price", action = "Click" } }, when = "!a && !b" },
+        "##);
+        let mut browser = browser();
+        run(config, &mut browser).await.unwrap();
+        assert_eq!(ran(&browser, "click #buy"), 0);
+        assert_eq!(ran(&browser, "click #price"), 1);
+    }
+
+    #[tokio::test]
+    async fn optional_failure_continues_the_group() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { action = { Find = { selector = "#missing", action = "Click" } }, optional = true },
+            { action = { Find = { selector = "#buy", action = "Click" } } },
+        "##);
+        let mut browser = browser();
+        run(config, &mut browser).await.unwrap();
+        assert_eq!(ran(&browser, "click #missing"), 1);
+        assert_eq!(ran(&browser, "click #buy"), 1);
+    }
+
+    #[tokio::test]
+    async fn required_failure_ends_the_group() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { action = { Find = { selector = "#missing", action = "Click" } } },
+            { action = { Find = { selector = "#buy", action = "Click" } } },
+        "##);
+        let mut browser = browser();
+        match run(config, &mut browser).await {
+            Err(BrowserOutcome::NoSuchElement(_)) => {},
+            other => panic!("expected NoSuchElement, got {:?}", other),
+        }
+        assert_eq!(ran(&browser, "click #buy"), 0);
+    }
+
+    #[tokio::test]
+    async fn end_stops_the_group_early() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { name = "buy", action = { Find = { selector = "#missing", action = "Click" } }, optional = true },
+            { action = "End", if_not_cond = "buy" },
+            { action = { Find = { selector = "#buy", action = "Click" } } },
+        "##);
+        let mut browser = browser();
+        match run(config, &mut browser).await {
+            Err(BrowserOutcome::EarlyEnd) => {},
+            other => panic!("expected EarlyEnd, got {:?}", other),
+        }
+        assert_eq!(ran(&browser, "click #buy"), 0);
+    }
+
+    #[tokio::test]
+    async fn skipped_end_does_not_stop_the_group() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { name = "buy", action = { Find = { selector = "#buy", action = "Click" } } },
+            { action = "End", if_not_cond = "buy" },
+            { action = { Find = { selector = "#price", action = "Click" } } },
+        "##);
+        let mut browser = browser();
+        run(config, &mut browser).await.unwrap();
+        assert_eq!(ran(&browser, "click #price"), 1);
+    }
+
+    #[tokio::test]
+    async fn wait_max_retries_until_it_runs_out() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { action = { Find = { selector = "#missing", action = "Click" } }, wait_max = 100, delay = 10 },
+        "##);
+        let mut browser = browser();
+        let start = Instant::now();
+        match run(config, &mut browser).await {
+            Err(BrowserOutcome::NoSuchElement(_)) => {},
+            other => panic!("expected NoSuchElement, got {:?}", other),
+        }
+        assert!(start.elapsed().as_millis() >= 100);
+        assert!(ran(&browser, "click #missing") > 1);
+    }
+
+    #[tokio::test]
+    async fn wait_max_does_not_retry_a_success() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { action = { Find = { selector = "#buy", action = "Click" } }, wait_max = 1000 },
+        "##);
+        let mut browser = browser();
+        run(config, &mut browser).await.unwrap();
+        assert_eq!(ran(&browser, "click #buy"), 1);
+    }
+
+    #[tokio::test]
+    async fn match_url_compares_one_component() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item?ref=nav" } } },
+            { action = { MatchUrl = "shop.test/item" } },
+            { action = { MatchUrl = { pattern = "shop.test", mode = "exact", component = "host" } } },
+            { action = { MatchUrl = { pattern = "/it?m", mode = "glob", component = "path" } } },
+            { action = { MatchUrl = { pattern = "^ref=\\w+$", mode = "regex", component = "query" } } },
+            { name = "full", action = { MatchUrl = { pattern = "shop.test", mode = "exact" } }, optional = true },
+            { action = { Find = { selector = "#buy", action = "Click" } }, if_not_cond = "full" },
+        "##);
+        let mut browser = MockBrowser::new(1)
+            .page("https://shop.test/item?ref=nav", MockPage::new().element("#buy", MockElement::new()));
+        run(config, &mut browser).await.unwrap();
+        assert_eq!(ran(&browser, "click #buy"), 1);
+    }
+
+    #[tokio::test]
+    async fn match_url_named_captures_become_variables() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item?ref=nav" } } },
+            { action = { MatchUrl = { pattern = "/(?P<page>\\w+)$", mode = "regex", component = "path" } } },
+            { action = { Find = { selector = "#buy", action = { Insert = "${page}" } } } },
+        "##);
+        let mut browser = MockBrowser::new(1)
+            .page("https://shop.test/item?ref=nav", MockPage::new().element("#buy", MockElement::new()));
+        run(config, &mut browser).await.unwrap();
+        assert_eq!(browser.value("#buy"), Some("item".to_string()));
+    }
+
+    #[tokio::test]
+    async fn match_url_mismatch_shows_the_component() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { action = { MatchUrl = { pattern = "/cart", component = "path" } } },
+        "##);
+        let mut browser = browser();
+        match run(config, &mut browser).await {
+            Err(BrowserOutcome::AssertionFailed{ check, expected, actual }) => {
+                assert_eq!(check, "MatchUrl path");
+                assert_eq!(expected, "contains \"/cart\"");
+                assert_eq!(actual, "\"/item\"");
+            },
+            other => panic!("expected AssertionFailed, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn script_result_is_stored_with_into() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { action = { Script = { source = "return 42;", into = "stock" } } },
+            { action = { Find = { selector = "#buy", action = { Insert = "${stock}" } } } },
+        "##);
+        let mut browser = browser();
+        browser.script_result = serde_json::json!(42);
+        run(config, &mut browser).await.unwrap();
+        assert_eq!(browser.value("#buy"), Some("42".to_string()));
+    }
+
+    #[tokio::test]
+    async fn falsy_script_result_fails_the_step() {
+        for result in &[serde_json::json!(null), serde_json::json!(false), serde_json::json!(0), serde_json::json!("")] {
+            let config = config(r##"
+                { action = { Navigate = { url = "https://shop.test/item" } } },
+                { action = { Script = { source = "return inStock();" } } },
+                { action = { Find = { selector = "#buy", action = "Click" } } },
+            "##);
+            let mut browser = browser();
+            browser.script_result = result.clone();
+            match run(config, &mut browser).await {
+                Err(BrowserOutcome::AssertionFailed{ check, actual, .. }) => {
+                    assert_eq!(check, "Script");
+                    assert_eq!(actual, result.to_string());
+                },
+                other => panic!("expected AssertionFailed for {}, got {:?}", result, other),
+            }
+            assert_eq!(ran(&browser, "click #buy"), 0);
+        }
+        for result in &[serde_json::json!("0"), serde_json::json!(-1), serde_json::json!([])] {
+            let config = config(r##"
+                { action = { Navigate = { url = "https://shop.test/item" } } },
+                { action = { Script = { source = "return inStock();" } } },
+            "##);
+            let mut browser = browser();
+            browser.script_result = result.clone();
+            run(config, &mut browser).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn script_args_are_interpolated() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { action = { Find = { selector = "#price", action = { Extract = { into = "price" } } } } },
+            { action = { Script = { source = "return arguments[0];", args = ["${price}", "plain"] } } },
+        "##);
+        let mut browser = browser();
+        run(config, &mut browser).await.unwrap();
+        assert_eq!(ran(&browser, r#"run_script false return arguments[0]; ["$499.99","plain"]"#), 1);
+    }
+
+    #[tokio::test]
+    async fn dialog_step_answers_the_open_dialog() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { action = { Dialog = { action = "accept", text = "2", expect_text = "How many" } } },
+            { action = { Find = { selector = "#buy", action = "Click" } } },
+        "##);
+        let mut browser = browser();
+        browser.dialog = Some("How many items?".to_string());
+        run(config, &mut browser).await.unwrap();
+        assert_eq!(ran(&browser, "answer_dialog Accept 2"), 1);
+        assert_eq!(ran(&browser, "click #buy"), 1);
+        assert!(browser.dialog.is_none());
+    }
+
+    #[tokio::test]
+    async fn dialog_step_fails_without_a_dialog() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { action = { Dialog = { action = "dismiss" } } },
+        "##);
+        let mut browser = browser();
+        match run(config, &mut browser).await {
+            Err(BrowserOutcome::AssertionFailed{ check, expected, actual }) => {
+                assert_eq!(check, "Dialog");
+                assert_eq!(expected, "an open dialog");
+                assert_eq!(actual, "none");
+            },
+            other => panic!("expected AssertionFailed, got {:?}", other),
+        }
+        assert!(browser.history.iter().all(|entry| !entry.starts_with("answer_dialog")));
+    }
+
+    #[tokio::test]
+    async fn dialog_step_checks_expect_text() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { action = { Dialog = { expect_text = "Remove" } } },
+        "##);
+        let mut browser = browser();
+        browser.dialog = Some("Leave this page?".to_string());
+        match run(config, &mut browser).await {
+            Err(BrowserOutcome::AssertionFailed{ check, expected, actual }) => {
+                assert_eq!(check, "Dialog");
+                assert_eq!(expected, "contains \"Remove\"");
+                assert_eq!(actual, "\"Leave this page?\"");
+            },
+            other => panic!("expected AssertionFailed, got {:?}", other),
+        }
+        assert_eq!(browser.dialog, Some("Leave this page?".to_string()));
+    }
+
+    #[tokio::test]
+    async fn unexpected_dialog_is_dismissed_and_fails_the_step() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { action = { Find = { selector = "#buy", action = "Click" } } },
+        "##);
+        let mut browser = browser();
+        browser.dialog = Some("Leave this page?".to_string());
+        match run(config, &mut browser).await {
+            Err(BrowserOutcome::UnhandledAlert(text)) => assert_eq!(text, "Leave this page?"),
+            other => panic!("expected UnhandledAlert, got {:?}", other),
+        }
+        assert_eq!(ran(&browser, "answer_dialog Dismiss "), 1);
+        assert!(browser.dialog.is_none());
+    }
+
+    #[tokio::test]
+    async fn on_dialog_answers_and_retries_the_step() {
+        let config = group_config("on_dialog = \"accept\", ", r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { action = { Find = { selector = "#buy", action = "Click" } } },
+        "##);
+        let mut browser = browser();
+        browser.dialog = Some("Leave this page?".to_string());
+        run(config, &mut browser).await.unwrap();
+        assert_eq!(ran(&browser, "answer_dialog Accept "), 1);
+        // the first click was blocked by the dialog
+        assert_eq!(ran(&browser, "click #buy"), 2);
+    }
+
+    #[tokio::test]
+    async fn on_dialog_gives_up_after_five_dialogs() {
+        let config = group_config("on_dialog = \"accept\", ", r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { action = { Find = { selector = "#nag", action = "Click" } } },
+        "##);
+        let mut browser = MockBrowser::new(1)
+            .page(SHOP, MockPage::new().element("#nag", MockElement::new().opens_dialog("Are you sure?")));
+        match run(config, &mut browser).await {
+            Err(BrowserOutcome::UnhandledAlert(text)) => assert_eq!(text, "Are you sure?"),
+            other => panic!("expected UnhandledAlert, got {:?}", other),
+        }
+        assert_eq!(ran(&browser, "answer_dialog Accept "), 5);
+        assert_eq!(ran(&browser, "answer_dialog Dismiss "), 1);
+        assert_eq!(ran(&browser, "click #nag"), 6);
+    }
+
+    #[tokio::test]
+    async fn steps_follow_navigations_and_frames() {
+        let config = config(r##"
+            { action = { Navigate = { url = "https://shop.test/item" } } },
+            { action = { MatchTitle = { value = "Item" } } },
+            { action = { AssertDisplayed = { selector = "#sold-out", displayed = false } } },
+            { action = { AssertEnabled = { selector = "#notify", enabled = false } } },
+            { action = { Find = { selector = "#checkout", action = { Extract = { into = "next", attr = "href" } } } } },
+            { action = { Find = { selector = "#checkout", action = "Click" } } },
+            { action = { MatchUrl = "${next}" } },
+            { action = { Find = { selector = "#pay", action = "SwitchFrame" } } },
+            { action = { Find = { selector = "#confirm", action = "Click" } } },
+        "##);
+        let mut browser = MockBrowser::new(1)
+            .page(SHOP, MockPage::new()
+                .title("Item")
+                .element("#sold-out", MockElement::new().hidden())
+                .element("#notify", MockElement::new().disabled())
+                .element("#checkout", MockElement::new().attr("href", "/checkout").navigates_to("https://shop.test/checkout")))
+            .page("https://shop.test/checkout", MockPage::new()
+                .element("#pay", MockElement::new().frame(MockPage::new().element("#confirm", MockElement::new()))));
+        run(config, &mut browser).await.unwrap();
+        assert_eq!(ran(&browser, "click #confirm"), 1);
+        assert_eq!(browser.frames(), vec!["#pay".to_string()]);
+    }
+}
@@ -0,0 +1,516 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// the run journal: one JSON object per line, appended as things happen so a
+// crash or a second signal loses at most the line being written. Every line
+// carries the run it belongs to (the start time of the run in ms) and its
+// own time, the rest depends on the record
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub run: u64,
+    pub time: u64,
+    #[serde(flatten)]
+    pub record: Record,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+pub enum Record {
+    RunStarted {
+        config: String,
+        groups: Vec<String>,
+        concurrent: bool,
+    },
+    GroupStarted {
+        group: String,
+        iteration: u64,
+    },
+    Step(StepRecord),
+    GroupEnded {
+        group: String,
+        iteration: u64,
+        // "ok", "failed" or "cancelled"
+        outcome: String,
+        duration_ms: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        kind: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    RunEnded {
+        // "finished", "stopped" (control API), "signal" or "error"
+        outcome: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StepRecord {
+    pub group: String,
+    pub iteration: u64,
+    pub step: String,
+    pub action: String,
+    // "succeeded", "failed" or "skipped"
+    pub outcome: String,
+    // why a skipped step did not run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(default)]
+    pub attempts: u32,
+    // the errors that were retried under wait_max, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retries: Vec<Retry>,
+    #[serde(default)]
+    pub duration_ms: u64,
+    // the BrowserOutcome variant of a failure
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frames: Vec<String>,
+    // files written by the step: screenshots, page dumps
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<String>,
+}
+
+// consecutive attempts failing the same way are counted, not repeated
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Retry {
+    pub error: String,
+    pub count: u32,
+}
+
+// add an error to the retry list of a step
+pub fn add_retry(retries: &mut Vec<Retry>, error: String) {
+    match retries.last_mut() {
+        Some(last) if last.error == error => last.count += 1,
+        _ => retries.push(Retry { error, count: 1 }),
+    }
+}
+
+// cheap to clone, every concurrent group writes through the same file.
+// A journal without a file ignores every record
+#[derive(Clone)]
+pub struct Journal {
+    file: Option<Arc<Mutex<File>>>,
+    run: u64,
+}
+
+impl Journal {
+    // empty path disables the journal
+    pub fn open(path: &str) -> Result<Journal, Box<dyn Error>> {
+        if path.is_empty() {
+            return Ok(Journal::disabled());
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|err| format!("cannot open journal {}: {}", path, err))?;
+        Ok(Journal { file: Some(Arc::new(Mutex::new(file))), run: now() })
+    }
+
+    pub fn disabled() -> Journal {
+        Journal { file: None, run: now() }
+    }
+
+    // a journal that cannot be written does not stop the run, it is logged
+    pub fn write(&self, record: Record) {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return,
+        };
+        let entry = Entry { run: self.run, time: now(), record };
+        let mut line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(err) => {
+                tracing::warn!(error = %err, "cannot serialize journal record");
+                return;
+            },
+        };
+        line.push('\n');
+        let mut file = match file.lock() {
+            Ok(file) => file,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Err(err) = file.write_all(line.as_bytes()) {
+            tracing::warn!(error = %err, "cannot write journal");
+        }
+    }
+}
+
+// ms since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or(0)
+}
+
+// every entry of the journal, lines that are not entries (a line cut short by
+// a crash) are skipped
+fn read(path: &str) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let file = File::open(path).map_err(|err| format!("cannot open journal {}: {}", path, err))?;
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Ok(entry) = serde_json::from_str(&line?) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+// how often each step of a group ended which way
+#[derive(Default)]
+struct StepSummary {
+    succeeded: u64,
+    failed: u64,
+    skipped: u64,
+    duration_ms: u64,
+    retries: u64,
+    // BrowserOutcome variant -> count
+    kinds: HashMap<String, u64>,
+}
+
+// print what happened during a run: the last one, or the one started at `run`
+pub fn summary(path: &str, run: Option<u64>) -> Result<(), Box<dyn Error>> {
+    for line in summary_lines(path, &read(path)?, run)? {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+// the lines summary prints, path is only for the errors
+fn summary_lines(path: &str, entries: &[Entry], run: Option<u64>) -> Result<Vec<String>, Box<dyn Error>> {
+    let run = match run.or_else(|| entries.last().map(|entry| entry.run)) {
+        Some(run) => run,
+        None => return Err(format!("journal {} is empty", path).into()),
+    };
+    let entries: Vec<&Entry> = entries.iter().filter(|entry| entry.run == run).collect();
+    if entries.is_empty() {
+        return Err(format!("no run {} in journal {}", run, path).into());
+    }
+
+    let end = entries.last().map(|entry| entry.time).unwrap_or(run);
+    let mut report = Vec::new();
+    report.push(format!("Run {} started {}, {} long", run, timestamp(run), duration(end.saturating_sub(run))));
+
+    // groups and steps in the order they first appear
+    let mut groups: Vec<String> = Vec::new();
+    let mut passes: HashMap<&str, (u64, u64, u64)> = HashMap::new();
+    let mut steps: Vec<(String, String)> = Vec::new();
+    let mut step_summaries: HashMap<(String, String), StepSummary> = HashMap::new();
+    let mut last_failure: Option<&StepRecord> = None;
+    let mut ended: Option<(&str, &Option<String>)> = None;
+
+    for entry in &entries {
+        match &entry.record {
+            Record::GroupEnded{group, outcome, ..} => {
+                if !groups.contains(group) {
+                    groups.push(group.clone());
+                }
+                let (ok, failed, cancelled) = passes.entry(group.as_str()).or_default();
+                match outcome.as_str() {
+                    "ok" => *ok += 1,
+                    "cancelled" => *cancelled += 1,
+                    _ => *failed += 1,
+                }
+            },
+            Record::Step(step) => {
+                let key = (step.group.clone(), step.step.clone());
+                if !steps.contains(&key) {
+                    steps.push(key.clone());
+                }
+                let summary = step_summaries.entry(key).or_default();
+                match step.outcome.as_str() {
+                    "succeeded" => summary.succeeded += 1,
+                    "skipped" => summary.skipped += 1,
+                    _ => {
+                        summary.failed += 1;
+                        if !step.optional {
+                            last_failure = Some(step);
+                        }
+                    },
+                }
+                summary.duration_ms += step.duration_ms;
+                summary.retries += step.retries.iter().map(|retry| retry.count as u64).sum::<u64>();
+                if let Some(kind) = &step.kind {
+                    *summary.kinds.entry(kind.clone()).or_default() += 1;
+                }
+            },
+            Record::RunEnded{outcome, error} => ended = Some((outcome.as_str(), error)),
+            _ => {},
+        }
+    }
+
+    for group in &groups {
+        let (ok, failed, cancelled) = passes.get(group.as_str()).cloned().unwrap_or_default();
+        report.push(String::new());
+        report.push(format!("Group [{}]: {} pass(es), {} ok, {} failed, {} cancelled", group, ok + failed + cancelled, ok, failed, cancelled));
+        for (step_group, step) in &steps {
+            if step_group != group {
+                continue;
+            }
+            let summary = &step_summaries[&(step_group.clone(), step.clone())];
+            let ran = summary.succeeded + summary.failed;
+            let average = summary.duration_ms.checked_div(ran).unwrap_or(0);
+            let mut kinds: Vec<(&String, &u64)> = summary.kinds.iter().collect();
+            kinds.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            let kinds: Vec<String> = kinds.iter().map(|(kind, count)| format!("{} x{}", kind, count)).collect();
+            report.push(format!("  {:<24} {:>5} ok {:>5} failed {:>5} skipped {:>6} retries {:>8} avg  {}",
+                if step.is_empty() { "(unnamed)" } else { step.as_str() },
+                summary.succeeded, summary.failed, summary.skipped, summary.retries,
+                duration(average), kinds.join(", ")));
+        }
+    }
+
+    if let Some(step) = last_failure {
+        report.push(String::new());
+        report.push(format!("Last failure: [{}] {} in pass {}: {}", step.group, step.step, step.iteration,
+            step.error.as_deref().unwrap_or("unknown error")));
+        if !step.url.is_empty() {
+            report.push(format!("  url: {}", step.url));
+        }
+        for artifact in &step.artifacts {
+            report.push(format!("  artifact: {}", artifact));
+        }
+    }
+
+    report.push(String::new());
+    match ended {
+        Some((outcome, Some(error))) => report.push(format!("Ended: {} ({})", outcome, error)),
+        Some((outcome, None)) => report.push(format!("Ended: {}", outcome)),
+        None => report.push("Ended: no end recorded, the run is still going or crashed".to_string()),
+    }
+    Ok(report)
+}
+
+// print the last `lines` entries, then keep printing new ones when following
+pub async fn tail(path: &str, lines: usize, follow: bool) -> Result<(), Box<dyn Error>> {
+    for line in tail_lines(&read(path)?, lines) {
+        println!("{}", line);
+    }
+    if !follow {
+        return Ok(());
+    }
+
+    let mut file = File::open(path).map_err(|err| format!("cannot open journal {}: {}", path, err))?;
+    let mut position = file.seek(SeekFrom::End(0))?;
+    let mut pending = String::new();
+    loop {
+        tokio::time::delay_for(Duration::from_millis(500)).await;
+        let length = file.metadata()?.len();
+        if length < position {
+            // the journal was truncated or replaced
+            file = File::open(path)?;
+            position = 0;
+        }
+        file.seek(SeekFrom::Start(position))?;
+        let mut reader = BufReader::new(&file);
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            position += line.len() as u64;
+            pending.push_str(&line);
+            line.clear();
+            // a line being written is finished on the next poll
+            if pending.ends_with('\n') {
+                if let Ok(entry) = serde_json::from_str::<Entry>(&pending) {
+                    println!("{}", describe(&entry));
+                }
+                pending.clear();
+            }
+        }
+    }
+}
+
+// the lines tail prints before following
+fn tail_lines(entries: &[Entry], lines: usize) -> Vec<String> {
+    entries[entries.len().saturating_sub(lines)..].iter().map(describe).collect()
+}
+
+// one line per entry for tail
+fn describe(entry: &Entry) -> String {
+    let time = timestamp(entry.time);
+    match &entry.record {
+        Record::RunStarted{config, groups, concurrent} => {
+            format!("{} run {} started with {} ({}{})", time, entry.run, config, groups.join(", "),
+                if *concurrent { ", concurrent" } else { "" })
+        },
+        Record::GroupStarted{group, iteration} => {
+            format!("{} [{}] pass {} started", time, group, iteration)
+        },
+        Record::Step(step) => {
+            let mut line = format!("{} [{}] pass {} {} {} {}", time, step.group, step.iteration,
+                if step.step.is_empty() { "(unnamed)" } else { step.step.as_str() }, step.action, step.outcome);
+            if let Some(reason) = &step.reason {
+                line.push_str(&format!(": {}", reason));
+            }
+            if step.outcome != "skipped" {
+                line.push_str(&format!(" in {}", duration(step.duration_ms)));
+            }
+            if step.attempts > 1 {
+                line.push_str(&format!(" after {} attempts", step.attempts));
+            }
+            if let Some(error) = &step.error {
+                line.push_str(&format!(": {}", error));
+            }
+            for artifact in &step.artifacts {
+                line.push_str(&format!(" -> {}", artifact));
+            }
+            line
+        },
+        Record::GroupEnded{group, iteration, outcome, duration_ms, error, ..} => {
+            match error {
+                Some(error) => format!("{} [{}] pass {} {} in {}: {}", time, group, iteration, outcome, duration(*duration_ms), error),
+                None => format!("{} [{}] pass {} {} in {}", time, group, iteration, outcome, duration(*duration_ms)),
+            }
+        },
+        Record::RunEnded{outcome, error} => {
+            match error {
+                Some(error) => format!("{} run {} ended: {} ({})", time, entry.run, outcome, error),
+                None => format!("{} run {} ended: {}", time, entry.run, outcome),
+            }
+        },
+    }
+}
+
+// "1.25s", "3m 20s"
+fn duration(ms: u64) -> String {
+    if ms < 60_000 {
+        format!("{:.2}s", ms as f64 / 1000.0)
+    } else {
+        format!("{}m {}s", ms / 60_000, ms % 60_000 / 1000)
+    }
+}
+
+// UTC "2021-03-04 05:06:07.890" for ms since the epoch
+fn timestamp(ms: u64) -> String {
+    let seconds = ms / 1000;
+    let (year, month, day) = civil_date((seconds / 86400) as i64);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}", year, month, day,
+        seconds % 86400 / 3600, seconds % 3600 / 60, seconds % 60, ms % 1000)
+}
+
+// days since 1970-01-01 to year, month, day (Howard Hinnant's civil_from_days)
+fn civil_date(days: i64) -> (i64, u64, u64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u64;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u64;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn step(name: &str, outcome: &str, error: Option<&str>) -> Record {
+        Record::Step(StepRecord {
+            group: "shop".to_string(),
+            iteration: 1,
+            step: name.to_string(),
+            action: "Find".to_string(),
+            outcome: outcome.to_string(),
+            reason: None,
+            optional: false,
+            attempts: 3,
+            retries: vec![Retry { error: "Element not found: (#buy)".to_string(), count: 2 }],
+            duration_ms: 1500,
+            kind: error.map(|_| "NoSuchElement".to_string()),
+            error: error.map(str::to_string),
+            url: "https://shop.test/item".to_string(),
+            frames: Vec::new(),
+            artifacts: vec!["artifacts/shop-1-buy".to_string()],
+        })
+    }
+
+    #[tokio::test]
+    async fn records_are_read_back_by_summary_and_tail() {
+        let path = std::env::temp_dir().join(format!("scraper-journal-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::remove_file(path).ok();
+
+        let journal = Journal::open(path).unwrap();
+        journal.write(Record::RunStarted { config: "sites.toml".to_string(), groups: vec!["shop".to_string()], concurrent: false });
+        journal.write(Record::GroupStarted { group: "shop".to_string(), iteration: 1 });
+        journal.write(step("open", "succeeded", None));
+        journal.write(step("buy", "failed", Some("Element not found: (#buy)")));
+        journal.write(Record::GroupEnded {
+            group: "shop".to_string(), iteration: 1, outcome: "failed".to_string(), duration_ms: 3000,
+            kind: Some("NoSuchElement".to_string()), error: Some("Element not found: (#buy)".to_string()),
+        });
+        journal.write(Record::RunEnded { outcome: "finished".to_string(), error: None });
+        // a line cut short by a crash
+        OpenOptions::new().append(true).open(path).unwrap().write_all(b"{\"run\":1,\"ti").unwrap();
+
+        let entries = read(path).unwrap();
+        assert_eq!(entries.len(), 6);
+        assert!(entries.iter().all(|entry| entry.run == journal.run));
+
+        let report = summary_lines(path, &entries, None).unwrap();
+        assert!(report[0].starts_with(&format!("Run {} started ", journal.run)), "{}", report[0]);
+        assert_eq!(report[2], "Group [shop]: 1 pass(es), 0 ok, 1 failed, 0 cancelled");
+        assert_eq!(report[3], format!("  {:<24} {:>5} ok {:>5} failed {:>5} skipped {:>6} retries {:>8} avg  ", "open", 1, 0, 0, 2, "1.50s"));
+        assert_eq!(report[4], format!("  {:<24} {:>5} ok {:>5} failed {:>5} skipped {:>6} retries {:>8} avg  NoSuchElement x1", "buy", 0, 1, 0, 2, "1.50s"));
+        assert_eq!(&report[6..], &[
+            "Last failure: [shop] buy in pass 1: Element not found: (#buy)",
+            "  url: https://shop.test/item",
+            "  artifact: artifacts/shop-1-buy",
+            "",
+            "Ended: finished",
+        ]);
+        summary(path, None).unwrap();
+        assert_eq!(summary(path, Some(1)).unwrap_err().to_string(), format!("no run 1 in journal {}", path));
+
+        let lines = tail_lines(&entries, 3);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with(" [shop] pass 1 buy Find failed in 1.50s after 3 attempts: Element not found: (#buy) -> artifacts/shop-1-buy"), "{}", lines[0]);
+        assert!(lines[1].ends_with(" [shop] pass 1 failed in 3.00s: Element not found: (#buy)"), "{}", lines[1]);
+        assert!(lines[2].ends_with(&format!(" run {} ended: finished", journal.run)), "{}", lines[2]);
+        assert_eq!(tail_lines(&entries, 100).len(), 6);
+        tail(path, 3, false).await.unwrap();
+
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn empty_journal_has_no_summary() {
+        assert_eq!(summary_lines("journal.jsonl", &[], None).unwrap_err().to_string(), "journal journal.jsonl is empty");
+        assert!(tail_lines(&[], 10).is_empty());
+    }
+
+    #[test]
+    fn civil_date_across_leap_days() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(-1), (1969, 12, 31));
+        // 2000 is a leap year, 2100 and 1900 are not
+        assert_eq!(civil_date(11015), (2000, 2, 28));
+        assert_eq!(civil_date(11016), (2000, 2, 29));
+        assert_eq!(civil_date(11017), (2000, 3, 1));
+        assert_eq!(civil_date(47540), (2100, 2, 28));
+        assert_eq!(civil_date(47541), (2100, 3, 1));
+        assert_eq!(civil_date(-25509), (1900, 2, 28));
+        assert_eq!(civil_date(-25508), (1900, 3, 1));
+        assert_eq!(civil_date(20088), (2024, 12, 31));
+        assert_eq!(civil_date(20089), (2025, 1, 1));
+    }
+
+    #[test]
+    fn timestamps_and_durations() {
+        assert_eq!(timestamp(0), "1970-01-01 00:00:00.000");
+        assert_eq!(timestamp(951_825_599_999), "2000-02-29 11:59:59.999");
+        assert_eq!(duration(1250), "1.25s");
+        assert_eq!(duration(200_000), "3m 20s");
+    }
+}
@@ -0,0 +1,89 @@
+// SendKeys text: characters are typed as they are, {Name} presses a special
+// key and {{ is a literal {. The names map to the WebDriver key codes
+const KEYS: [(&str, char); 20] = [
+    ("Backspace", '\u{E003}'),
+    ("Tab", '\u{E004}'),
+    ("Return", '\u{E006}'),
+    ("Enter", '\u{E007}'),
+    ("Shift", '\u{E008}'),
+    ("Control", '\u{E009}'),
+    ("Alt", '\u{E00A}'),
+    ("Escape", '\u{E00C}'),
+    ("Space", '\u{E00D}'),
+    ("PageUp", '\u{E00E}'),
+    ("PageDown", '\u{E00F}'),
+    ("End", '\u{E010}'),
+    ("Home", '\u{E011}'),
+    ("ArrowLeft", '\u{E012}'),
+    ("ArrowUp", '\u{E013}'),
+    ("ArrowRight", '\u{E014}'),
+    ("ArrowDown", '\u{E015}'),
+    ("Insert", '\u{E016}'),
+    ("Delete", '\u{E017}'),
+    // releases Shift, Control and Alt pressed earlier in the same text
+    ("Null", '\u{E000}'),
+];
+
+// the text WebDriver has to be sent, or why it cannot be
+pub fn translate(text: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        if rest.starts_with('{') {
+            result.push('{');
+            rest = &rest[1..];
+            continue;
+        }
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => return Err(format!("unterminated {{ in \"{}\"", text)),
+        };
+        let name = &rest[..end];
+        match KEYS.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)) {
+            Some((_, code)) => result.push(*code),
+            None => return Err(format!("unknown key {{{}}}", name)),
+        }
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_become_key_codes() {
+        assert_eq!(translate("abc").unwrap(), "abc");
+        assert_eq!(translate("42{Enter}").unwrap(), "42\u{E007}");
+        assert_eq!(translate("{control}a{NULL}{Delete}").unwrap(), "\u{E009}a\u{E000}\u{E017}");
+        assert_eq!(translate("").unwrap(), "");
+    }
+
+    #[test]
+    fn double_brace_is_a_literal_brace() {
+        assert_eq!(translate("{{").unwrap(), "{");
+        assert_eq!(translate("{{Enter}").unwrap(), "{Enter}");
+        assert_eq!(translate("a{{{Tab}}").unwrap(), "a{\u{E004}}");
+        assert_eq!(translate("}").unwrap(), "}");
+    }
+
+    #[test]
+    fn unknown_names_are_errors() {
+        assert_eq!(translate("{Enterr}"), Err("unknown key {Enterr}".to_string()));
+        assert_eq!(translate("{}"), Err("unknown key {}".to_string()));
+        assert_eq!(translate("{ Enter }"), Err("unknown key { Enter }".to_string()));
+    }
+
+    #[test]
+    fn unclosed_brace_is_an_error() {
+        assert_eq!(translate("a{Enter"), Err("unterminated { in \"a{Enter\"".to_string()));
+        assert_eq!(translate("{"), Err("unterminated { in \"{\"".to_string()));
+        assert_eq!(translate("{{{"), Err("unterminated { in \"{{{\"".to_string()));
+    }
+}
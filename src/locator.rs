@@ -0,0 +1,145 @@
+use crate::config::{By, Selector};
+
+// every selector strategy becomes a CSS selector or an XPath expression, so
+// WebDriver and scripts find exactly the same elements
+pub enum Query {
+    Css(String),
+    XPath(String),
+}
+
+pub fn query(selector: &Selector) -> Query {
+    let value = &selector.value;
+    match selector.by {
+        By::Css => Query::Css(value.clone()),
+        By::XPath => Query::XPath(value.clone()),
+        By::Id => Query::Css(format!("[id={}]", css_string(value))),
+        By::Name => Query::Css(format!("[name={}]", css_string(value))),
+        By::LinkText => Query::XPath(format!("//a[normalize-space(.)={}]", xpath_string(&normalize(value)))),
+        By::PartialLinkText => Query::XPath(format!("//a[contains(normalize-space(.), {})]", xpath_string(&normalize(value)))),
+        // without the not() every ancestor up to <html> would match as well
+        By::Text => {
+            let text = xpath_string(&normalize(value));
+            Query::XPath(format!("//*[normalize-space(.)={0} and not(*[normalize-space(.)={0}])]", text))
+        },
+    }
+}
+
+// the IFRAME or FRAME element with this name or id
+pub fn frame_named(name: &str) -> Selector {
+    let name = css_string(name);
+    Selector {
+        by: By::Css,
+        value: format!("iframe[name={0}], frame[name={0}], iframe[id={0}], frame[id={0}]", name),
+        index: 0,
+    }
+}
+
+// defines locate(xpath, query, index), the script side of query(). Scripts
+// using it take script_args() as their first three arguments
+pub const LOCATE_JS: &str = "
+function locate(xpath, query, index) {
+    if (xpath) {
+        return document.evaluate(query, document, null, XPathResult.ORDERED_NODE_SNAPSHOT_TYPE, null).snapshotItem(index);
+    }
+    return document.querySelectorAll(query)[index] || null;
+}
+";
+
+pub fn script_args(selector: &Selector) -> Vec<serde_json::Value> {
+    let (xpath, query) = match query(selector) {
+        Query::Css(query) => (false, query),
+        Query::XPath(query) => (true, query),
+    };
+    vec![serde_json::json!(xpath), serde_json::json!(query), serde_json::json!(selector.index)]
+}
+
+// collapse whitespace the way normalize-space() does
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn css_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// XPath 1.0 has no escapes, a text with both quote kinds is built with concat()
+fn xpath_string(text: &str) -> String {
+    if !text.contains('\'') {
+        return format!("'{}'", text);
+    }
+    if !text.contains('"') {
+        return format!("\"{}\"", text);
+    }
+    let parts: Vec<String> = text.split('\'').map(|part| format!("'{}'", part)).collect();
+    format!("concat({})", parts.join(", \"'\", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selector(by: By, value: &str) -> Selector {
+        Selector { by, value: value.to_string(), index: 0 }
+    }
+
+    fn xpath(selector: &Selector) -> String {
+        match query(selector) {
+            Query::XPath(xpath) => xpath,
+            Query::Css(css) => panic!("expected XPath, got CSS {}", css),
+        }
+    }
+
+    fn css(selector: &Selector) -> String {
+        match query(selector) {
+            Query::Css(css) => css,
+            Query::XPath(xpath) => panic!("expected CSS, got XPath {}", xpath),
+        }
+    }
+
+    #[test]
+    fn xpath_strings_pick_a_quote_the_text_lacks() {
+        let cases = [
+            ("Add to cart", "'Add to cart'"),
+            ("Don't wait", "\"Don't wait\""),
+            ("Say \"yes\"", "'Say \"yes\"'"),
+            ("Don't say \"no\"", "concat('Don', \"'\", 't say \"no\"')"),
+            ("'a' \"b\"", "concat('', \"'\", 'a', \"'\", ' \"b\"')"),
+            ("C:\\path", "'C:\\path'"),
+        ];
+        for (text, expected) in cases.iter() {
+            assert_eq!(xpath_string(text), *expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn css_strings_escape_quotes_and_backslashes() {
+        let cases = [
+            ("buy", "[id=\"buy\"]"),
+            ("it's", "[id=\"it's\"]"),
+            ("a\"b", "[id=\"a\\\"b\"]"),
+            ("a\\b", "[id=\"a\\\\b\"]"),
+            ("\\\"", "[id=\"\\\\\\\"\"]"),
+        ];
+        for (id, expected) in cases.iter() {
+            assert_eq!(css(&selector(By::Id, id)), *expected, "{}", id);
+        }
+        assert_eq!(css(&selector(By::Name, "q\"ty")), "[name=\"q\\\"ty\"]");
+    }
+
+    #[test]
+    fn text_selectors_normalize_and_quote() {
+        assert_eq!(xpath(&selector(By::LinkText, "  Don't \n buy ")), "//a[normalize-space(.)=\"Don't buy\"]");
+        assert_eq!(xpath(&selector(By::PartialLinkText, "It's \"new\"")),
+            "//a[contains(normalize-space(.), concat('It', \"'\", 's \"new\"'))]");
+        assert_eq!(xpath(&selector(By::Text, "Buy")),
+            "//*[normalize-space(.)='Buy' and not(*[normalize-space(.)='Buy'])]");
+    }
+
+    #[test]
+    fn frame_names_are_css_strings() {
+        let frame = frame_named("pay\"ment\\");
+        assert_eq!(frame.by, By::Css);
+        assert_eq!(frame.value, "iframe[name=\"pay\\\"ment\\\\\"], frame[name=\"pay\\\"ment\\\\\"], \
+            iframe[id=\"pay\\\"ment\\\\\"], frame[id=\"pay\\\"ment\\\\\"]");
+    }
+}
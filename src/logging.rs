@@ -0,0 +1,90 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+
+use crate::config::{LogFormat, LogLevel, LogSettings};
+
+// emit a tracing event at a level only known at run time, the tracing macros
+// need it as a constant
+macro_rules! event_at {
+    ($level:expr, $($arg:tt)+) => {
+        match $level {
+            crate::config::LogLevel::Trace => tracing::trace!($($arg)+),
+            crate::config::LogLevel::Debug => tracing::debug!($($arg)+),
+            crate::config::LogLevel::Info => tracing::info!($($arg)+),
+            crate::config::LogLevel::Warn => tracing::warn!($($arg)+),
+            crate::config::LogLevel::Error => tracing::error!($($arg)+),
+        }
+    };
+}
+pub(crate) use event_at;
+
+// console output at settings.level, plus JSON lines appended to settings.file
+// at settings.file_level when a file is set
+pub fn init(settings: &LogSettings) -> Result<(), Box<dyn Error>> {
+    let console = match settings.format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_target(false)
+            .with_filter(filter(settings.level))
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_filter(filter(settings.level))
+            .boxed(),
+    };
+
+    let file = if settings.file.is_empty() {
+        None
+    } else {
+        let file = OpenOptions::new().create(true).append(true).open(&settings.file)
+            .map_err(|err| format!("cannot open log file {}: {}", settings.file, err))?;
+        let file = Arc::new(Mutex::new(file));
+        Some(tracing_subscriber::fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_ansi(false)
+            .with_writer(move || FileWriter(file.clone()))
+            .with_filter(filter(settings.file_level)))
+    };
+
+    tracing_subscriber::registry()
+        .with(console)
+        .with(file)
+        .try_init()?;
+    Ok(())
+}
+
+fn filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Trace => LevelFilter::TRACE,
+        LogLevel::Debug => LevelFilter::DEBUG,
+        LogLevel::Info => LevelFilter::INFO,
+        LogLevel::Warn => LevelFilter::WARN,
+        LogLevel::Error => LevelFilter::ERROR,
+    }
+}
+
+// every event is written whole and unbuffered, so nothing is lost when a
+// second signal exits the process right away
+struct FileWriter(Arc<Mutex<File>>);
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.0.lock() {
+            Ok(mut file) => file.write(buf),
+            Err(poisoned) => poisoned.into_inner().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.0.lock() {
+            Ok(mut file) => file.flush(),
+            Err(poisoned) => poisoned.into_inner().flush(),
+        }
+    }
+}
@@ -1,56 +1,153 @@
+mod api;
+mod cli;
 mod config;
+mod condition;
+mod control;
 mod step;
+mod artifacts;
+mod assert;
+mod backend;
 mod browser;
+mod cancel;
 mod group;
+mod journal;
+mod keys;
+mod locator;
+mod logging;
+mod notify;
+mod price;
+mod process;
+mod runner;
+mod shutdown;
+mod validate;
+mod vars;
+mod wait;
+// in-memory backend, --dry-run and the tests use it
+mod mock;
 
+use artifacts::Artifacts;
+use cancel::CancelToken;
+use control::Control;
+use cli::{Command, Options};
 use config::*;
 use group::*;
-use browser::*;
+use backend::BrowserBackend;
+use journal::{Journal, Record};
+use notify::Notifiers;
+use mock::MockBrowser;
 use std::error::Error;
-use std::time::Duration;
+use std::sync::Arc;
+use structopt::StructOpt;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let options = Options::from_args();
 
-    let config = load_config("sites.toml")?;
+    // lint the config without starting a browser
+    if options.check {
+        let (_, diagnostics) = validate::check_config(&options.config, &options.groups)?;
+        for diagnostic in &diagnostics {
+            println!("{}", diagnostic);
+        }
+        let errors = diagnostics.iter().filter(|diagnostic| diagnostic.severity == validate::Severity::Error).count();
+        println!("{} error(s), {} warning(s)", errors, diagnostics.len() - errors);
+        std::process::exit(if errors > 0 { 1 } else { 0 });
+    }
 
-    let mut browser = Browser::new(
-        config.groups.len(),Duration::from_millis(config.timeout),
-        &config.profile,
-        &config.screenshot_path,
-        config.marionette_port,
-    ).await?;
+    match &options.command {
+        Some(Command::Summary{journal, run}) => {
+            return journal::summary(&journal_path(&options, journal)?, *run);
+        },
+        Some(Command::Tail{journal, lines, follow}) => {
+            return journal::tail(&journal_path(&options, journal)?, *lines, *follow).await;
+        },
+        None => {},
+    }
 
-    loop {
-        for (index,group) in config.groups.iter().enumerate() {
+    let (config, diagnostics) = validate::check_config(&options.config, &options.groups)?;
+    logging::init(&config.log)?;
+    report_diagnostics(&options.config, &diagnostics)?;
+    let groups = config.select_groups(&options.groups)?;
+    let config = Arc::new(config);
+    let names: Vec<String> = groups.iter().map(|index| config.groups[*index].name.clone()).collect();
 
-            if let Err(err) = browser.switch_tab(index).await {
-                println!("Group [{}] tab switch error {}, restarting", group.name, err);
-                browser.restart().await?;
+    if options.dry_run {
+        let cancel = CancelToken::new();
+        let (journal, artifacts, notifiers) = (Journal::disabled(), Artifacts::disabled(), Notifiers::dry_run());
+        let control = Control::new(&options.config, config.clone(), notifiers.clone(), cancel.clone(), &names);
+        let mut browser = MockBrowser::permissive(groups.len());
+        for (tab,index) in groups.iter().enumerate() {
+            let group = &config.groups[*index];
+            browser.switch_tab(tab).await?;
+            let pass = Pass { iteration: 1, cancel: &cancel, journal: &journal, artifacts: &artifacts, notifiers: &notifiers, control: &control };
+            match process_group(group, &mut browser, &pass).await {
+                Ok(_) => tracing::info!(group = %group.name, "dry run ended OK"),
+                Err(err) => tracing::info!(group = %group.name, error = %err, "dry run ended"),
             }
+        }
+        return Ok(());
+    }
+
+    let journal = Journal::open(&config.journal)?;
+    let notifiers = Notifiers::new(&config.notifiers)?;
+    journal.write(Record::RunStarted {
+        config: options.config.clone(),
+        groups: names.clone(),
+        concurrent: config.concurrent,
+    });
+
+    // cancelled by SIGINT/SIGTERM, or a stop through the control API
+    let cancel = CancelToken::new();
+    let control = Control::new(&options.config, config.clone(), notifiers, cancel.clone(), &names);
+    if let Some(settings) = &config.control {
+        api::serve(settings, control.clone())?;
+    }
+    shutdown::handle_signals(cancel.clone())?;
 
-            // decide whether to continue looping over groups
-            match process_group(group, &mut browser).await {
-                Ok(_) => {
-                    println!("Ended OK");
-                    browser.close().await?;
-                    return Ok(());
-                },
-                Err(err) => {
-                    match err  {
-                        unexpected @ BrowserOutcome::Timeout(_) 
-                        | unexpected @ BrowserOutcome::Unexpected(_) 
-                        | unexpected @ BrowserOutcome::ReCaptchaIssue(_)
-                        | unexpected @ BrowserOutcome::ClientLost => {
-                            println!("Group [{}] unexpected error, restarting: {}", group.name, unexpected);
-                            browser.restart().await?;
-                        },
-                        _ => {
-                            // silently continue looping, expected error
-                        },
-                    }
-                },
-            };
+    let result = if config.concurrent {
+        runner::run_concurrent(config.clone(), &groups, options.iterations(), &cancel, &journal, &control).await
+    } else {
+        runner::run_sequential(&config, &groups, options.iterations(), &cancel, &journal, &control).await
+    };
+
+    let outcome = if control.stop_requested() {
+        "stopped"
+    } else if cancel.is_cancelled() {
+        "signal"
+    } else if result.is_err() {
+        "error"
+    } else {
+        "finished"
+    };
+    journal.write(Record::RunEnded {
+        outcome: outcome.to_string(),
+        error: result.as_ref().err().map(|err| err.to_string()),
+    });
+
+    if control.stop_requested() {
+        tracing::info!("stopped through the control API");
+        return result;
+    }
+
+    if cancel.is_cancelled() {
+        if let Err(err) = &result {
+            tracing::error!(error = %err, "error while stopping");
         }
+        tracing::info!("stopped by signal");
+        shutdown::flush();
+        std::process::exit(shutdown::EXIT_INTERRUPTED);
+    }
+    result
+}
+
+// the journal named on the command line, or the one set in the config
+fn journal_path(options: &Options, journal: &Option<String>) -> Result<String, Box<dyn Error>> {
+    if let Some(journal) = journal {
+        return Ok(journal.clone());
+    }
+    let (config, _) = validate::check_config(&options.config, &[])?;
+    if config.journal.is_empty() {
+        return Err(format!("{} sets no journal, pass --journal", options.config).into());
     }
-}
\ No newline at end of file
+    Ok(config.journal)
+}
@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::backend::BrowserBackend;
+use crate::browser::{no_such_element, BrowserOutcome};
+use crate::config::{DialogAction, Selector, SelectBy};
+
+// an element of a fake page. Clicking it can navigate the tab, and if it
+// holds a page of its own it can be entered like an IFRAME
+#[derive(Debug, Clone, Default)]
+pub struct MockElement {
+    pub value: String,
+    pub text: String,
+    pub attributes: HashMap<String, String>,
+    pub navigates_to: Option<String>,
+    pub frame: Option<MockPage>,
+    pub hidden: bool,
+    pub disabled: bool,
+    pub checked: bool,
+    // text of the dialog a click opens, the click is reported as interrupted by it
+    pub opens_dialog: Option<String>,
+}
+
+impl MockElement {
+    pub fn new() -> MockElement {
+        MockElement::default()
+    }
+}
+
+// builders for scripting pages in tests
+#[cfg(test)]
+impl MockElement {
+    pub fn text(mut self, text: &str) -> MockElement {
+        self.text = text.to_string();
+        self
+    }
+
+    pub fn attr(mut self, name: &str, value: &str) -> MockElement {
+        self.attributes.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn navigates_to(mut self, url: &str) -> MockElement {
+        self.navigates_to = Some(url.to_string());
+        self
+    }
+
+    pub fn frame(mut self, page: MockPage) -> MockElement {
+        self.frame = Some(page);
+        self
+    }
+
+    pub fn hidden(mut self) -> MockElement {
+        self.hidden = true;
+        self
+    }
+
+    pub fn disabled(mut self) -> MockElement {
+        self.disabled = true;
+        self
+    }
+
+    pub fn opens_dialog(mut self, text: &str) -> MockElement {
+        self.opens_dialog = Some(text.to_string());
+        self
+    }
+}
+
+// a fake document, elements are looked up by the selector as it is displayed:
+// "#buy", "xpath=//button", "text=Buy Now[1]" for the second match
+#[derive(Debug, Clone, Default)]
+pub struct MockPage {
+    pub title: String,
+    pub elements: HashMap<String, MockElement>,
+}
+
+impl MockPage {
+    pub fn new() -> MockPage {
+        MockPage::default()
+    }
+}
+
+#[cfg(test)]
+impl MockPage {
+    pub fn title(mut self, title: &str) -> MockPage {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn element(mut self, selector: &str, element: MockElement) -> MockPage {
+        self.elements.insert(selector.to_string(), element);
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MockTab {
+    url: String,
+    page: MockPage,
+    // the IFRAMEs entered and their pages, innermost last
+    frames: Vec<(String, MockPage)>,
+}
+
+impl MockTab {
+    fn document(&mut self) -> &mut MockPage {
+        match self.frames.last_mut() {
+            Some((_, frame)) => frame,
+            None => &mut self.page,
+        }
+    }
+}
+
+// scriptable in-memory BrowserBackend. Pages are registered by URL before a
+// run, every call made by the step engine is recorded in `history`
+#[derive(Debug)]
+pub struct MockBrowser {
+    pages: HashMap<String, MockPage>,
+    tabs: Vec<MockTab>,
+    current_tab: usize,
+    // treat every selector as an existing element, used by --dry-run
+    permissive: bool,
+    pub screenshots: usize,
+    // directory the screenshot paths point into, nothing is written there
+    pub screenshot_path: String,
+    pub history: Vec<String>,
+    // what every Script step returns
+    pub script_result: serde_json::Value,
+    // text of the open dialog
+    pub dialog: Option<String>,
+}
+
+impl MockBrowser {
+    pub fn new(tabs: usize) -> MockBrowser {
+        let blank = MockTab {
+            url: "about:blank".to_string(),
+            page: MockPage::new(),
+            frames: Vec::new(),
+        };
+        MockBrowser {
+            pages: HashMap::new(),
+            tabs: vec![blank; tabs.max(1)],
+            current_tab: 0,
+            permissive: false,
+            screenshots: 0,
+            screenshot_path: String::new(),
+            history: Vec::new(),
+            script_result: serde_json::Value::Bool(true),
+            dialog: None,
+        }
+    }
+
+    // a backend where every element exists and nothing navigates, so the
+    // steps a group would run can be followed without scripting any pages
+    pub fn permissive(tabs: usize) -> MockBrowser {
+        let mut browser = MockBrowser::new(tabs);
+        browser.permissive = true;
+        browser
+    }
+
+    #[cfg(test)]
+    pub fn page(mut self, url: &str, page: MockPage) -> MockBrowser {
+        self.pages.insert(url.to_string(), page);
+        self
+    }
+
+    // value of a field after Insert steps ran on the current document
+    #[cfg(test)]
+    pub fn value(&mut self, selector: &str) -> Option<String> {
+        self.tab().document().elements.get(selector).map(|element| element.value.clone())
+    }
+
+    fn tab(&mut self) -> &mut MockTab {
+        &mut self.tabs[self.current_tab]
+    }
+
+    // unknown URLs load an empty page. Query strings are ignored when the
+    // exact URL is not registered so anti_cache navigation still resolves
+    fn load(&self, url: &str) -> MockPage {
+        let without_query = url.split('?').next().unwrap_or(url);
+        self.pages.get(url)
+            .or_else(|| self.pages.get(without_query))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // while a dialog is open the page cannot be used, like WebDriver's
+    // unexpected alert open
+    fn element(&mut self, selector: &Selector) -> Result<MockElement, BrowserOutcome> {
+        if let Some(text) = &self.dialog {
+            return Err(BrowserOutcome::UnhandledAlert(text.clone()));
+        }
+        let key = selector.to_string();
+        let permissive = self.permissive;
+        let elements = &mut self.tab().document().elements;
+        if permissive && !elements.contains_key(&key) {
+            elements.insert(key.clone(), MockElement::new());
+        }
+        match elements.get(&key) {
+            Some(element) => Ok(element.clone()),
+            None => Err(no_such_element(format!("mock page has no element {}", selector))),
+        }
+    }
+
+    fn element_mut(&mut self, selector: &Selector) -> Result<&mut MockElement, BrowserOutcome> {
+        self.element(selector)?;
+        match self.tab().document().elements.get_mut(&selector.to_string()) {
+            Some(element) => Ok(element),
+            None => Err(no_such_element(format!("mock page has no element {}", selector))),
+        }
+    }
+}
+
+#[async_trait]
+impl BrowserBackend for MockBrowser {
+    async fn goto(&mut self, dest: &str) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("goto {}", dest));
+        let page = self.load(dest);
+        let tab = self.tab();
+        tab.url = dest.to_string();
+        tab.page = page;
+        tab.frames.clear();
+        Ok(())
+    }
+
+    async fn find(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("find {}", selector));
+        self.element(selector)?;
+        Ok(())
+    }
+
+    async fn click(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("click {}", selector));
+        let element = self.element(selector)?;
+        if let Some(text) = element.opens_dialog {
+            self.dialog = Some(text.clone());
+            return Err(BrowserOutcome::UnhandledAlert(text));
+        }
+        if let Some(url) = element.navigates_to {
+            let page = self.load(&url);
+            let tab = self.tab();
+            tab.url = url;
+            tab.page = page;
+            tab.frames.clear();
+        }
+        Ok(())
+    }
+
+    async fn insert(&mut self, selector: &Selector, value: &str) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("insert {} {}", selector, value));
+        self.element_mut(selector)?.value = value.to_string();
+        Ok(())
+    }
+
+    async fn clear(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("clear {}", selector));
+        self.element_mut(selector)?.value.clear();
+        Ok(())
+    }
+
+    // special keys are appended as their WebDriver codes
+    async fn send_keys(&mut self, selector: &Selector, keys: &str) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("send_keys {} {}", selector, keys));
+        self.element_mut(selector)?.value.push_str(keys);
+        Ok(())
+    }
+
+    // the value becomes the option value, text or index
+    async fn select(&mut self, selector: &Selector, option: &SelectBy) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("select {} {:?}", selector, option));
+        self.element_mut(selector)?.value = match option {
+            SelectBy::Value(value) | SelectBy::Text(value) => value.clone(),
+            SelectBy::Index(index) => index.to_string(),
+        };
+        Ok(())
+    }
+
+    async fn set_checked(&mut self, selector: &Selector, checked: bool) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("set_checked {} {}", selector, checked));
+        self.element_mut(selector)?.checked = checked;
+        Ok(())
+    }
+
+    async fn hover(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("hover {}", selector));
+        self.element(selector)?;
+        Ok(())
+    }
+
+    async fn scroll_into_view(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("scroll_into_view {}", selector));
+        self.element(selector)?;
+        Ok(())
+    }
+
+    async fn double_click(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("double_click {}", selector));
+        self.element(selector)?;
+        Ok(())
+    }
+
+    async fn right_click(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("right_click {}", selector));
+        self.element(selector)?;
+        Ok(())
+    }
+
+    async fn find_attribute(&mut self, selector: &Selector, attr: &str) -> Result<Option<String>, BrowserOutcome> {
+        self.history.push(format!("find_attribute {} {}", selector, attr));
+        let element = self.element(selector)?;
+        if attr == "value" && !element.attributes.contains_key(attr) {
+            return Ok(Some(element.value));
+        }
+        Ok(element.attributes.get(attr).cloned())
+    }
+
+    async fn find_text(&mut self, selector: &Selector) -> Result<String, BrowserOutcome> {
+        self.history.push(format!("find_text {}", selector));
+        Ok(self.element(selector)?.text)
+    }
+
+    async fn current_url(&mut self) -> Result<String, BrowserOutcome> {
+        Ok(self.tab().url.clone())
+    }
+
+    async fn run_script(&mut self, source: &str, args: Vec<serde_json::Value>, is_async: bool) -> Result<serde_json::Value, BrowserOutcome> {
+        self.history.push(format!("run_script {} {} {}", is_async, source, serde_json::Value::Array(args)));
+        Ok(self.script_result.clone())
+    }
+
+    async fn page_source(&mut self) -> Result<String, BrowserOutcome> {
+        let document = self.tab().document();
+        let mut selectors: Vec<&String> = document.elements.keys().collect();
+        selectors.sort();
+        let elements: Vec<String> = selectors.iter().map(|selector| format!("<!-- {} -->", selector)).collect();
+        Ok(format!("<html><head><title>{}</title></head><body>{}</body></html>", document.title, elements.join("")))
+    }
+
+    async fn window_handles(&mut self) -> Result<Vec<String>, BrowserOutcome> {
+        Ok((0..self.tabs.len()).map(|index| format!("mock-tab-{}", index)).collect())
+    }
+
+    async fn console_log(&mut self) -> Result<Option<Vec<String>>, BrowserOutcome> {
+        Ok(None)
+    }
+
+    async fn dialog_text(&mut self) -> Result<Option<String>, BrowserOutcome> {
+        Ok(self.dialog.clone())
+    }
+
+    async fn answer_dialog(&mut self, action: DialogAction, text: &str) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("answer_dialog {:?} {}", action, text));
+        match self.dialog.take() {
+            Some(_) => Ok(()),
+            None => Err(BrowserOutcome::Unexpected(fantoccini::error::CmdError::Standard(
+                webdriver::error::WebDriverError::new(webdriver::error::ErrorStatus::NoSuchAlert, "mock has no dialog open"),
+            ))),
+        }
+    }
+
+    // pages load instantly
+    async fn ready_state(&mut self) -> Result<String, BrowserOutcome> {
+        Ok("complete".to_string())
+    }
+
+    async fn title(&mut self) -> Result<String, BrowserOutcome> {
+        Ok(self.tab().document().title.clone())
+    }
+
+    async fn is_displayed(&mut self, selector: &Selector) -> Result<bool, BrowserOutcome> {
+        self.history.push(format!("is_displayed {}", selector));
+        Ok(!self.element(selector)?.hidden)
+    }
+
+    async fn is_enabled(&mut self, selector: &Selector) -> Result<bool, BrowserOutcome> {
+        self.history.push(format!("is_enabled {}", selector));
+        Ok(!self.element(selector)?.disabled)
+    }
+
+    // selectors are exact keys, so a page holds at most one match
+    async fn count(&mut self, selector: &Selector) -> Result<usize, BrowserOutcome> {
+        self.history.push(format!("count {}", selector));
+        match self.element(selector) {
+            Ok(_) => Ok(1),
+            Err(_) => Ok(0),
+        }
+    }
+
+    async fn refresh(&mut self) -> Result<(), BrowserOutcome> {
+        self.history.push("refresh".to_string());
+        let url = self.tab().url.clone();
+        let page = self.load(&url);
+        let tab = self.tab();
+        tab.page = page;
+        tab.frames.clear();
+        Ok(())
+    }
+
+    async fn switch_frame(&mut self, selector: &Selector) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("switch_frame {}", selector));
+        let frame = self.element(selector)?.frame.unwrap_or_default();
+        self.tab().frames.push((selector.to_string(), frame));
+        Ok(())
+    }
+
+    // the frames of a document are its elements holding a page, in selector order
+    async fn switch_frame_index(&mut self, index: usize) -> Result<(), BrowserOutcome> {
+        self.history.push(format!("switch_frame_index {}", index));
+        let permissive = self.permissive;
+        let document = self.tab().document();
+        let mut frames: Vec<(&String, &MockPage)> = document.elements.iter()
+            .filter_map(|(selector, element)| element.frame.as_ref().map(|frame| (selector, frame)))
+            .collect();
+        frames.sort_by(|a, b| a.0.cmp(b.0));
+        let frame = match frames.get(index) {
+            Some((_, frame)) => (*frame).clone(),
+            None if permissive => MockPage::new(),
+            None => return Err(no_such_element(format!("mock page has no frame {}", index))),
+        };
+        self.tab().frames.push((format!("frame {}", index), frame));
+        Ok(())
+    }
+
+    async fn parent_frame(&mut self) -> Result<(), BrowserOutcome> {
+        self.history.push("parent_frame".to_string());
+        self.tab().frames.pop();
+        Ok(())
+    }
+
+    async fn top_window(&mut self) -> Result<(), BrowserOutcome> {
+        self.history.push("top_window".to_string());
+        self.tab().frames.clear();
+        Ok(())
+    }
+
+    fn frames(&self) -> Vec<String> {
+        self.tabs[self.current_tab].frames.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    async fn screenshot(&mut self, full_page: bool, selector: Option<&Selector>, name: &str) -> Result<String, BrowserOutcome> {
+        let shown = selector.map(|selector| selector.to_string()).unwrap_or_default();
+        self.history.push(format!("screenshot {} {} {}", full_page, shown, name));
+        if let Some(selector) = selector {
+            self.element(selector)?;
+        }
+        self.screenshots += 1;
+        let file = format!("mock-{}-{}.png", self.screenshots, name);
+        Ok(std::path::Path::new(&self.screenshot_path).join(file).to_string_lossy().into_owned())
+    }
+
+    async fn switch_tab(&mut self, index: usize) -> Result<(), BrowserOutcome> {
+        if index >= self.tabs.len() {
+            return Err(BrowserOutcome::TabDoesNotExist(index));
+        }
+        self.current_tab = index;
+        self.tab().frames.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,564 @@
+use async_trait::async_trait;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use crate::config::{NotifierSettings, SmtpTls};
+use crate::journal;
+
+// a notifier that does not answer within this is given up on
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(20);
+
+// errors are Send so concurrent groups can notify from their own task
+pub type NotifyError = Box<dyn Error + Send + Sync>;
+
+// what happened, every backend gets all of it. The webhook body is this as JSON
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    // "step" for Notify steps, "success" or "restarts"
+    pub event: String,
+    pub group: String,
+    pub message: String,
+    // ms since the unix epoch
+    pub time: u64,
+}
+
+impl Notification {
+    pub fn new(event: &str, group: &str, message: String) -> Notification {
+        Notification {
+            event: event.to_string(),
+            group: group.to_string(),
+            message,
+            time: journal::now(),
+        }
+    }
+
+    // replace ${event}, ${group} and ${message} in a subject or argument
+    fn fill(&self, template: &str) -> String {
+        template
+            .replace("${event}", &self.event)
+            .replace("${group}", &self.group)
+            .replace("${message}", &self.message)
+    }
+}
+
+// one way of telling someone, configured by a [notifiers.<channel>] table
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifyError>;
+}
+
+// every configured notifier by channel name
+#[derive(Clone)]
+pub struct Notifiers {
+    channels: Arc<Vec<(String, Box<dyn Notifier>)>>,
+    // --dry-run logs notifications instead of sending them
+    dry_run: bool,
+}
+
+impl Notifiers {
+    pub fn new(settings: &HashMap<String, NotifierSettings>) -> Result<Notifiers, Box<dyn Error>> {
+        let mut channels: Vec<(String, Box<dyn Notifier>)> = Vec::new();
+        for (name, settings) in settings {
+            let notifier: Box<dyn Notifier> = match settings {
+                NotifierSettings::Webhook{url, headers} => Box::new(Webhook {
+                    client: reqwest::Client::builder().timeout(NOTIFY_TIMEOUT).build()?,
+                    url: url.clone(),
+                    headers: headers.clone(),
+                }),
+                NotifierSettings::Smtp{server, port, tls, username, password, from, to, subject} => Box::new(Smtp {
+                    server: server.clone(),
+                    port: *port,
+                    tls: *tls,
+                    username: username.clone(),
+                    password: password.clone(),
+                    from: from.clone(),
+                    to: to.clone(),
+                    subject: subject.clone(),
+                }),
+                NotifierSettings::Command{program, args} => Box::new(Command {
+                    program: program.clone(),
+                    args: args.clone(),
+                }),
+            };
+            channels.push((name.clone(), notifier));
+        }
+        channels.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(Notifiers { channels: Arc::new(channels), dry_run: false })
+    }
+
+    pub fn dry_run() -> Notifiers {
+        Notifiers { channels: Arc::new(Vec::new()), dry_run: true }
+    }
+
+    // send to the notifier named channel, or to all of them when channel is
+    // empty. Every notifier is tried, the errors are joined
+    pub async fn send(&self, channel: &str, notification: &Notification) -> Result<(), String> {
+        if self.dry_run {
+            tracing::info!(channel, group = %notification.group, event = %notification.event,
+                message = %notification.message, "notification not sent, dry run");
+            return Ok(());
+        }
+
+        let mut sent = 0;
+        let mut errors = Vec::new();
+        for (name, notifier) in self.channels.iter().filter(|(name, _)| channel.is_empty() || name == channel) {
+            sent += 1;
+            match notifier.notify(notification).await {
+                Ok(_) => tracing::debug!(channel = %name, event = %notification.event, "notification sent"),
+                Err(err) => errors.push(format!("{}: {}", name, err)),
+            }
+        }
+
+        if sent == 0 {
+            return Err(if channel.is_empty() { "no notifiers configured".to_string() } else { format!("no notifier named {}", channel) });
+        }
+        if !errors.is_empty() {
+            return Err(errors.join(", "));
+        }
+        Ok(())
+    }
+
+    // the runner's own notifications, a failure is only logged
+    pub async fn send_all(&self, channels: &[String], notification: &Notification) {
+        for channel in channels {
+            if let Err(err) = self.send(channel, notification).await {
+                tracing::warn!(channel = %channel, event = %notification.event, error = %err, "notification not sent");
+            }
+        }
+    }
+}
+
+struct Webhook {
+    client: reqwest::Client,
+    url: String,
+    headers: HashMap<String, String>,
+}
+
+#[async_trait]
+impl Notifier for Webhook {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifyError> {
+        let mut request = self.client.post(&self.url).json(notification);
+        for (name, value) in &self.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+struct Command {
+    program: String,
+    args: Vec<String>,
+}
+
+#[async_trait]
+impl Notifier for Command {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifyError> {
+        let output = tokio::process::Command::new(&self.program)
+            .args(self.args.iter().map(|arg| notification.fill(arg)))
+            .env("NOTIFY_EVENT", &notification.event)
+            .env("NOTIFY_GROUP", &notification.group)
+            .env("NOTIFY_MESSAGE", &notification.message)
+            .kill_on_drop(true)
+            .output();
+        let output = timeout(NOTIFY_TIMEOUT, output).await
+            .map_err(|_| format!("{} did not exit within {}s", self.program, NOTIFY_TIMEOUT.as_secs()))?
+            .map_err(|err| format!("cannot run {}: {}", self.program, err))?;
+        if !output.status.success() {
+            return Err(format!("{} exited with {}: {}", self.program, output.status,
+                String::from_utf8_lossy(&output.stderr).trim()).into());
+        }
+        Ok(())
+    }
+}
+
+struct Smtp {
+    server: String,
+    port: u16,
+    tls: SmtpTls,
+    username: String,
+    password: String,
+    from: String,
+    to: Vec<String>,
+    subject: String,
+}
+
+#[async_trait]
+impl Notifier for Smtp {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifyError> {
+        timeout(NOTIFY_TIMEOUT, self.send_mail(notification)).await
+            .map_err(|_| format!("{}:{} did not answer within {}s", self.server, self.port, NOTIFY_TIMEOUT.as_secs()))?
+    }
+}
+
+impl Smtp {
+    async fn send_mail(&self, notification: &Notification) -> Result<(), NotifyError> {
+        let mut stream: Box<dyn Stream> = Box::new(TcpStream::connect((self.server.as_str(), self.port)).await?);
+        if self.tls == SmtpTls::Implicit {
+            stream = tls(stream, &self.server).await?;
+        }
+        let mut session = SmtpSession { stream: BufReader::new(stream) };
+        session.reply('2').await?;
+        session.command("EHLO localhost", '2').await?;
+
+        if self.tls == SmtpTls::StartTls {
+            session.command("STARTTLS", '2').await?;
+            let stream = tls(session.stream.into_inner(), &self.server).await?;
+            session = SmtpSession { stream: BufReader::new(stream) };
+            session.command("EHLO localhost", '2').await?;
+        }
+
+        if !self.username.is_empty() {
+            let credentials = base64::encode(format!("\0{}\0{}", self.username, self.password));
+            session.command(&format!("AUTH PLAIN {}", credentials), '2').await?;
+        }
+
+        session.command(&format!("MAIL FROM:<{}>", self.from), '2').await?;
+        for to in &self.to {
+            session.command(&format!("RCPT TO:<{}>", to), '2').await?;
+        }
+        session.command("DATA", '3').await?;
+        session.command(&self.message(notification), '2').await?;
+        // the mail is accepted, a server hanging up early does not matter
+        session.command("QUIT", '2').await.ok();
+        Ok(())
+    }
+
+    // headers and body up to the final "." line. Body lines starting with a
+    // dot get a second one so they cannot end the mail early
+    fn message(&self, notification: &Notification) -> String {
+        let body: Vec<String> = notification.message.replace("\r\n", "\n").split('\n')
+            .map(|line| if line.starts_with('.') { format!(".{}", line) } else { line.to_string() })
+            .collect();
+        let to: Vec<String> = self.to.iter().map(|to| format!("<{}>", one_line(to))).collect();
+        format!("From: <{}>\r\nTo: {}\r\nSubject: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{}\r\n.",
+            one_line(&self.from), to.join(", "), header_text(&notification.fill(&self.subject)), body.join("\r\n"))
+    }
+}
+
+// a TCP connection before or after TLS
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+async fn tls(stream: Box<dyn Stream>, server: &str) -> Result<Box<dyn Stream>, NotifyError> {
+    let connector = tokio_tls::TlsConnector::from(native_tls::TlsConnector::new()?);
+    Ok(Box::new(connector.connect(server, stream).await?))
+}
+
+struct SmtpSession {
+    stream: BufReader<Box<dyn Stream>>,
+}
+
+impl SmtpSession {
+    // read a reply, continuation lines have a '-' after the code, and check
+    // the first digit of its code
+    async fn reply(&mut self, expected: char) -> Result<String, NotifyError> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                return Err("connection closed by the server".into());
+            }
+            reply.push_str(&line);
+            if line.len() < 4 || line.as_bytes()[3] != b'-' {
+                break;
+            }
+        }
+        if !reply.starts_with(expected) {
+            return Err(format!("unexpected reply: {}", reply.trim()).into());
+        }
+        Ok(reply)
+    }
+
+    async fn command(&mut self, command: &str, expected: char) -> Result<String, NotifyError> {
+        self.stream.write_all(format!("{}\r\n", command).as_bytes()).await?;
+        self.stream.flush().await?;
+        self.reply(expected).await
+    }
+}
+
+// non-ASCII subjects are sent as an RFC 2047 encoded word
+fn header_text(text: &str) -> String {
+    let text = one_line(text);
+    if text.is_ascii() {
+        text
+    } else {
+        format!("=?utf-8?B?{}?=", base64::encode(text))
+    }
+}
+
+// a header value with its line breaks folded into spaces. A group name or
+// message filled into the subject could otherwise add headers or start the body
+fn one_line(text: &str) -> String {
+    text.split(['\r', '\n']).filter(|part| !part.is_empty()).collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    fn notification(message: &str) -> Notification {
+        Notification { event: "success".to_string(), group: "amazon".to_string(), message: message.to_string(), time: 1 }
+    }
+
+    // what the test server saw, it gives up when the client never connects
+    async fn served<T>(server: tokio::task::JoinHandle<T>) -> T {
+        timeout(Duration::from_secs(10), server).await.expect("the client never finished").unwrap()
+    }
+
+    async fn listener() -> (TcpListener, u16) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        (listener, port)
+    }
+
+    // one HTTP request, answered with status. Returns its head and body
+    async fn http_server(mut listener: TcpListener, status: &'static str) -> (String, String) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        let mut head = String::new();
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            if line == "\r\n" || line.is_empty() {
+                break;
+            }
+            head.push_str(&line);
+        }
+        let length = head.lines()
+            .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|length| length.trim().parse::<usize>().unwrap()))
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).await.unwrap();
+        stream.get_mut().write_all(format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).as_bytes()).await.unwrap();
+        (head, String::from_utf8(body).unwrap())
+    }
+
+    fn webhook(port: u16) -> Webhook {
+        let mut headers = HashMap::new();
+        headers.insert("X-Token".to_string(), "secret".to_string());
+        Webhook { client: reqwest::Client::new(), url: format!("http://127.0.0.1:{}/hooks/autobuy", port), headers }
+    }
+
+    #[tokio::test]
+    async fn webhook_posts_the_notification_as_json() {
+        let (listener, port) = listener().await;
+        let server = tokio::spawn(http_server(listener, "204 No Content"));
+        webhook(port).notify(&notification("ended OK")).await.unwrap();
+
+        let (head, body) = served(server).await;
+        assert!(head.starts_with("POST /hooks/autobuy HTTP/1.1\r\n"), "{}", head);
+        let head = head.to_lowercase();
+        assert!(head.contains("content-type: application/json"), "{}", head);
+        assert!(head.contains("x-token: secret"), "{}", head);
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body, serde_json::json!({"event": "success", "group": "amazon", "message": "ended OK", "time": 1}));
+    }
+
+    #[tokio::test]
+    async fn webhook_error_status_fails() {
+        let (listener, port) = listener().await;
+        let server = tokio::spawn(http_server(listener, "500 Internal Server Error"));
+        let err = webhook(port).notify(&notification("ended OK")).await.unwrap_err();
+        assert!(err.to_string().contains("500"), "{}", err);
+        served(server).await;
+    }
+
+    fn smtp(port: u16, tls: SmtpTls) -> Smtp {
+        Smtp {
+            server: "127.0.0.1".to_string(),
+            port,
+            tls,
+            username: "bot".to_string(),
+            password: "pass".to_string(),
+            from: "bot@example.com".to_string(),
+            to: vec!["me@example.com".to_string(), "you@example.com".to_string()],
+            subject: "[${group}] ${event} ✓".to_string(),
+        }
+    }
+
+    async fn write(stream: &mut BufReader<TcpStream>, reply: &str) {
+        stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+    }
+
+    async fn read_line(stream: &mut BufReader<TcpStream>) -> Option<String> {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await.unwrap() == 0 {
+            return None;
+        }
+        Some(line.trim_end_matches("\r\n").to_string())
+    }
+
+    // a plain text server that accepts everything, every line it received
+    // is returned, the mail lines included
+    async fn smtp_server(mut listener: TcpListener) -> Vec<String> {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        let mut received = Vec::new();
+        write(&mut stream, "220-mail.test ESMTP\r\n220 ready\r\n").await;
+        while let Some(line) = read_line(&mut stream).await {
+            received.push(line.clone());
+            match line.as_str() {
+                "DATA" => {
+                    write(&mut stream, "354 end with .\r\n").await;
+                    while let Some(line) = read_line(&mut stream).await {
+                        received.push(line.clone());
+                        if line == "." {
+                            break;
+                        }
+                    }
+                    write(&mut stream, "250 queued\r\n").await;
+                },
+                "QUIT" => {
+                    write(&mut stream, "221 bye\r\n").await;
+                    break;
+                },
+                line if line.starts_with("EHLO") => write(&mut stream, "250-mail.test\r\n250-AUTH PLAIN\r\n250 8BITMIME\r\n").await,
+                line if line.starts_with("AUTH") => write(&mut stream, "235 accepted\r\n").await,
+                _ => write(&mut stream, "250 ok\r\n").await,
+            }
+        }
+        received
+    }
+
+    #[tokio::test]
+    async fn smtp_sends_the_whole_dialogue() {
+        let (listener, port) = listener().await;
+        let server = tokio::spawn(smtp_server(listener));
+        smtp(port, SmtpTls::None).notify(&notification("ordered\n.hidden line\n..two dots\nend")).await.unwrap();
+        let received = served(server).await;
+
+        let credentials = base64::encode("\0bot\0pass");
+        let subject = format!("Subject: =?utf-8?B?{}?=", base64::encode("[amazon] success ✓"));
+        let expected = vec![
+            "EHLO localhost".to_string(),
+            format!("AUTH PLAIN {}", credentials),
+            "MAIL FROM:<bot@example.com>".to_string(),
+            "RCPT TO:<me@example.com>".to_string(),
+            "RCPT TO:<you@example.com>".to_string(),
+            "DATA".to_string(),
+            "From: <bot@example.com>".to_string(),
+            "To: <me@example.com>, <you@example.com>".to_string(),
+            subject,
+            "MIME-Version: 1.0".to_string(),
+            "Content-Type: text/plain; charset=utf-8".to_string(),
+            "Content-Transfer-Encoding: 8bit".to_string(),
+            "".to_string(),
+            "ordered".to_string(),
+            "..hidden line".to_string(),
+            "...two dots".to_string(),
+            "end".to_string(),
+            ".".to_string(),
+            "QUIT".to_string(),
+        ];
+        assert_eq!(received, expected);
+    }
+
+    #[tokio::test]
+    async fn smtp_rejected_recipient_fails() {
+        let (mut listener, port) = listener().await;
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            write(&mut stream, "220 ready\r\n").await;
+            while let Some(line) = read_line(&mut stream).await {
+                if line.starts_with("RCPT") {
+                    write(&mut stream, "550-no such user\r\n550 mailbox unavailable\r\n").await;
+                } else {
+                    write(&mut stream, "250 ok\r\n").await;
+                }
+            }
+        });
+        let err = smtp(port, SmtpTls::None).notify(&notification("ordered")).await.unwrap_err();
+        assert_eq!(err.to_string(), "unexpected reply: 550-no such user\r\n550 mailbox unavailable");
+        served(server).await;
+    }
+
+    #[tokio::test]
+    async fn smtp_starttls_upgrades_before_authenticating() {
+        let (mut listener, port) = listener().await;
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut received = Vec::new();
+            write(&mut stream, "220 ready\r\n").await;
+            received.push(read_line(&mut stream).await.unwrap());
+            write(&mut stream, "250-mail.test\r\n250 STARTTLS\r\n").await;
+            received.push(read_line(&mut stream).await.unwrap());
+            write(&mut stream, "220 go ahead\r\n").await;
+            // the next bytes are a TLS record, not the credentials
+            let mut record = [0u8; 1];
+            stream.read_exact(&mut record).await.unwrap();
+            (received, record[0])
+        });
+        // the test server has no certificate, the handshake cannot finish
+        assert!(smtp(port, SmtpTls::StartTls).notify(&notification("ordered")).await.is_err());
+        let (received, record) = served(server).await;
+        assert_eq!(received, vec!["EHLO localhost", "STARTTLS"]);
+        // 0x16: handshake
+        assert_eq!(record, 0x16);
+    }
+
+    #[test]
+    fn line_breaks_cannot_add_headers() {
+        let mut notifier = smtp(25, SmtpTls::None);
+        notifier.subject = "[${group}] ${message}".to_string();
+        let notification = Notification {
+            event: "success".to_string(),
+            group: "amazon\r\nBcc: everyone@example.com".to_string(),
+            message: "bought\r\n\r\nfake body\nline".to_string(),
+            time: 1,
+        };
+        let message = notifier.message(&notification);
+        let (head, body) = message.split_at(message.find("\r\n\r\n").unwrap());
+        let headers: Vec<&str> = head.split("\r\n").collect();
+        assert_eq!(headers.len(), 6, "{:?}", headers);
+        assert_eq!(headers[2], "Subject: [amazon Bcc: everyone@example.com] bought fake body line");
+        assert_eq!(body, "\r\n\r\nbought\r\n\r\nfake body\r\nline\r\n.");
+        assert_eq!(header_text("a\rb\n\nc\r\n"), "a b c");
+        assert_eq!(header_text("✓\r\nBcc: x"), format!("=?utf-8?B?{}?=", base64::encode("✓ Bcc: x")));
+    }
+
+    #[test]
+    fn only_non_ascii_subjects_are_encoded() {
+        assert_eq!(header_text("[amazon] success"), "[amazon] success");
+        assert_eq!(header_text("Bestellung ✓"), format!("=?utf-8?B?{}?=", base64::encode("Bestellung ✓")));
+    }
+
+    #[cfg(unix)]
+    fn command(script: &str) -> Command {
+        Command {
+            program: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string(), "${group}".to_string(), "${message}".to_string()],
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn command_gets_the_notification_as_arguments_and_environment() {
+        let script = r#"test "$0" = amazon && test "$1" = "ended OK" && test "$NOTIFY_EVENT" = success && test "$NOTIFY_MESSAGE" = "ended OK""#;
+        command(script).notify(&notification("ended OK")).await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn command_exit_status_is_checked() {
+        let err = command("echo no display >&2; exit 3").notify(&notification("ended OK")).await.unwrap_err();
+        let err = err.to_string();
+        assert!(err.starts_with("sh exited with exit status: 3"), "{}", err);
+        assert!(err.ends_with(": no display"), "{}", err);
+
+        let missing = Command { program: "no-such-notifier-program".to_string(), args: vec![] };
+        let err = missing.notify(&notification("ended OK")).await.unwrap_err();
+        assert!(err.to_string().starts_with("cannot run no-such-notifier-program: "), "{}", err);
+    }
+}
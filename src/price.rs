@@ -0,0 +1,135 @@
+// reading prices out of element text such as "$1,299.99", "1.299,99 €",
+// "CHF 1'299.–" or "$12.00 - $15.50"
+
+// characters used to group thousands, next to ',' and '.'
+const GROUP_SEPARATORS: [char; 4] = [' ', '\'', '\u{a0}', '\u{202f}'];
+
+// the highest price in text, so a range is judged by its upper end.
+// When currency is set the text has to mention it (symbol or code)
+pub fn parse_price(text: &str, currency: &str) -> Result<f64, String> {
+    if !currency.is_empty() && !text.to_lowercase().contains(&currency.to_lowercase()) {
+        return Err(format!("\"{}\" is not in {}", text.trim(), currency));
+    }
+
+    let prices: Vec<f64> = numbers(text).iter()
+        .filter_map(|number| parse_number(number))
+        .collect();
+
+    prices.into_iter()
+        .fold(None, |highest: Option<f64>, price| Some(highest.map_or(price, |highest| highest.max(price))))
+        .ok_or_else(|| format!("no price in \"{}\"", text.trim()))
+}
+
+// runs of digits and the separators between them
+fn numbers(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut numbers = Vec::new();
+    let mut current = String::new();
+
+    for (index, c) in chars.iter().enumerate() {
+        let next_is_digit = chars.get(index + 1).is_some_and(|next| next.is_ascii_digit());
+        let separator = *c == '.' || *c == ',' || GROUP_SEPARATORS.contains(c);
+        if c.is_ascii_digit() || (!current.is_empty() && next_is_digit && separator) {
+            current.push(*c);
+        } else if !current.is_empty() {
+            numbers.push(current.clone());
+            current.clear();
+        }
+    }
+    if !current.is_empty() {
+        numbers.push(current);
+    }
+    numbers
+}
+
+// the last of ',' and '.' is the decimal separator when both appear. A lone
+// separator followed by exactly three digits groups thousands ("1,299") when
+// it could start a grouped number, one to three digits without a leading
+// zero. Otherwise it is the decimal one ("12,50", "0.125", "1299.500")
+fn parse_number(number: &str) -> Option<f64> {
+    let number: String = number.chars().filter(|c| !GROUP_SEPARATORS.contains(c)).collect();
+
+    let decimal = match (number.rfind('.'), number.rfind(',')) {
+        (Some(dot), Some(comma)) => Some(dot.max(comma)),
+        (Some(position), None) | (None, Some(position)) => {
+            let separator = &number[position..position + 1];
+            let single = number.matches(separator).count() == 1;
+            let leading = &number[..position];
+            let grouped = number.len() - position - 1 == 3
+                && (1..=3).contains(&leading.len())
+                && !leading.starts_with('0');
+            if single && !grouped {
+                Some(position)
+            } else {
+                None
+            }
+        },
+        (None, None) => None,
+    };
+
+    let normalized: String = match decimal {
+        Some(position) => {
+            let whole: String = number[..position].chars().filter(|c| c.is_ascii_digit()).collect();
+            format!("{}.{}", whole, &number[position + 1..])
+        },
+        None => number.chars().filter(|c| c.is_ascii_digit()).collect(),
+    };
+    normalized.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(text: &str) -> f64 {
+        parse_price(text, "").unwrap()
+    }
+
+    #[test]
+    fn us_prices() {
+        assert_eq!(price("$1,299.99"), 1299.99);
+        assert_eq!(price("$1,299"), 1299.0);
+        assert_eq!(price("$1,234,567"), 1234567.0);
+        assert_eq!(price("$12.50"), 12.5);
+        assert_eq!(price("$599"), 599.0);
+    }
+
+    #[test]
+    fn three_decimals_are_not_thousands() {
+        assert_eq!(price("0.125"), 0.125);
+        assert_eq!(price("0,125 €"), 0.125);
+        assert_eq!(price("1299.500"), 1299.5);
+    }
+
+    #[test]
+    fn european_prices() {
+        assert_eq!(price("1.299,99 €"), 1299.99);
+        assert_eq!(price("1.299 €"), 1299.0);
+        assert_eq!(price("12,50 €"), 12.5);
+        assert_eq!(price("1.234.567,89 €"), 1234567.89);
+        assert_eq!(price("1 299,00 €"), 1299.0);
+        assert_eq!(price("1\u{a0}299,00\u{a0}€"), 1299.0);
+        assert_eq!(price("CHF 1'299.–"), 1299.0);
+    }
+
+    #[test]
+    fn range_is_judged_by_its_upper_end() {
+        assert_eq!(price("$12.00 - $15.50"), 15.5);
+        assert_eq!(price("Was $899.99, now $599.99"), 899.99);
+    }
+
+    #[test]
+    fn currency_has_to_be_mentioned() {
+        assert_eq!(parse_price("$1,299.99", "$").unwrap(), 1299.99);
+        assert_eq!(parse_price("1.299,99 EUR", "eur").unwrap(), 1299.99);
+        assert_eq!(parse_price("CHF 1'299.–", "CHF").unwrap(), 1299.0);
+        assert!(parse_price("1.299,99 €", "$").is_err());
+        assert!(parse_price("£12.50", "USD").is_err());
+    }
+
+    #[test]
+    fn text_without_a_number_is_an_error() {
+        assert!(parse_price("Currently unavailable", "").is_err());
+        assert!(parse_price("$", "$").is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// default location of the driver executables when the config does not set one
+#[cfg(windows)]
+pub const DEFAULT_GECKODRIVER: &str = ".\\geckodriver.exe";
+#[cfg(not(windows))]
+pub const DEFAULT_GECKODRIVER: &str = "geckodriver";
+#[cfg(windows)]
+pub const DEFAULT_CHROMEDRIVER: &str = ".\\chromedriver.exe";
+#[cfg(not(windows))]
+pub const DEFAULT_CHROMEDRIVER: &str = "chromedriver";
+
+// PIDs of every driver currently running, so a forced exit can still
+// clean up without going through the DriverProcess handles
+static RUNNING: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+// a WebDriver server spawned by the bot. The handle owns the Child so the
+// driver, and every browser process the driver launched, can be terminated
+// by PID instead of by image name. Dropping the handle terminates them too.
+pub struct DriverProcess {
+    child: Child,
+    killed: bool,
+}
+
+impl DriverProcess {
+    pub fn spawn(path: &str, args: &[String]) -> Result<DriverProcess, Box<dyn Error>> {
+        let mut command = Command::new(path);
+        // stderr is inherited, the driver reports startup errors such as a
+        // port in use there
+        command.args(args).stdout(Stdio::null());
+
+        // give the driver its own process group so the browser it launches
+        // can be terminated together with it, without touching anything else
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+
+        let child = command.spawn()
+            .map_err(|err| format!("failed to start driver ({}): {}", path, err))?;
+        if let Ok(mut running) = RUNNING.lock() {
+            running.push(child.id());
+        }
+        Ok(DriverProcess { child, killed: false })
+    }
+
+    // the driver starts listening a moment after it is spawned, connecting
+    // before that fails the whole session, so poll the port first
+    pub async fn wait_until_listening(&mut self, port: u16, max: Duration) -> Result<(), Box<dyn Error>> {
+        let address = SocketAddr::from(([127, 0, 0, 1], port));
+        let start_time = Instant::now();
+
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                return Err(format!("driver exited early with {}", status).into());
+            }
+            let connect = tokio::net::TcpStream::connect(address);
+            if let Ok(Ok(_)) = tokio::time::timeout(Duration::from_millis(200), connect).await {
+                return Ok(());
+            }
+            if start_time.elapsed() >= max {
+                return Err(format!("driver did not listen on port {} within {:?}", port, max).into());
+            }
+            tokio::time::delay_for(Duration::from_millis(100)).await;
+        }
+    }
+
+    // terminate the driver and the browser processes it started, then reap it.
+    // Only done once, the PID could belong to something else afterwards
+    pub fn kill(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.killed {
+            return Ok(());
+        }
+        self.killed = true;
+        let pid = self.child.id();
+        if let Ok(mut running) = RUNNING.lock() {
+            running.retain(|running_pid| *running_pid != pid);
+        }
+        kill_tree(pid)?;
+        self.child.wait()?;
+        Ok(())
+    }
+}
+
+// terminate every driver and browser still running, for when the process is
+// about to exit without unwinding
+pub fn kill_all() {
+    if let Ok(mut running) = RUNNING.lock() {
+        for pid in running.drain(..) {
+            kill_tree(pid).ok();
+        }
+    }
+}
+
+impl Drop for DriverProcess {
+    fn drop(&mut self) {
+        self.kill().ok();
+    }
+}
+
+// the driver was spawned as a process group leader, so its PID is also the
+// group id and a negative PID signals the driver and the browser together
+#[cfg(unix)]
+fn kill_tree(pid: u32) -> Result<(), Box<dyn Error>> {
+    let result = unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+    if result != 0 {
+        let err = std::io::Error::last_os_error();
+        // ESRCH: the group is already gone
+        if err.raw_os_error() != Some(libc::ESRCH) {
+            return Err(err.into());
+        }
+    }
+    Ok(())
+}
+
+// /t follows the parent PID chain, so only browsers started by this driver are closed
+#[cfg(windows)]
+fn kill_tree(pid: u32) -> Result<(), Box<dyn Error>> {
+    Command::new("taskkill")
+    .args(&["/f", "/t", "/pid", pid.to_string().as_str()])
+    .output()?;
+    Ok(())
+}
@@ -0,0 +1,254 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::backend::BrowserBackend;
+use crate::browser::*;
+use crate::cancel::CancelToken;
+use crate::config::*;
+use crate::group::*;
+use crate::artifacts::Artifacts;
+use crate::control::{Control, Next};
+use crate::journal::Journal;
+use crate::notify::{Notification, Notifiers};
+
+// how often a paused group checks whether it may run again
+const CONTROL_POLL: Duration = Duration::from_millis(250);
+
+// errors that leave the session in an unknown state, the browser is restarted
+pub fn needs_restart(outcome: &BrowserOutcome) -> bool {
+    matches!(outcome,
+        BrowserOutcome::Timeout(_)
+        | BrowserOutcome::Unexpected(_)
+        | BrowserOutcome::ReCaptchaIssue(_)
+        | BrowserOutcome::ClientLost
+        | BrowserOutcome::TabDoesNotExist(_))
+}
+
+// all groups share one browser, one tab per group, visited in turn.
+// groups are indexes into config.groups, every pass runs them as defined by
+// the config of the last reload. Cancelling stops at the current step
+pub async fn run_sequential(config: &Config, groups: &[usize], iterations: Option<u64>, cancel: &CancelToken, journal: &Journal, control: &Control) -> Result<(), Box<dyn Error>> {
+    let mut browser = Browser::new(
+        groups.len(),Duration::from_millis(config.timeout),
+        config.driver_settings(),
+        &config.screenshot_path,
+    ).await?;
+
+    let names: Vec<String> = groups.iter().map(|index| config.groups[*index].name.clone()).collect();
+    // stopping a group through the control API cancels only its own token
+    let tokens: Vec<CancelToken> = names.iter().map(|name| control.register(name, cancel)).collect();
+    // restarts since the last pass that did not need one
+    let mut restarts = 0;
+    let mut iteration = 0;
+    'passes: while !cancel.is_cancelled() && iterations.is_none_or(|max| iteration < max) {
+        let next: Vec<Next> = names.iter().map(|name| control.next_pass(name)).collect();
+        if next.iter().all(|next| *next == Next::Stop) {
+            break;
+        }
+        // every group left is paused
+        if !next.contains(&Next::Run) {
+            if cancel.sleep(CONTROL_POLL).await.is_err() {
+                break;
+            }
+            continue;
+        }
+        iteration += 1;
+
+        let current = control.config();
+        let artifacts = Artifacts::new(&current);
+        let notifiers = control.notifiers();
+        for (tab,name) in names.iter().enumerate() {
+            if cancel.is_cancelled() {
+                break 'passes;
+            }
+            if next[tab] != Next::Run {
+                continue;
+            }
+            let group = match current.groups.iter().find(|group| &group.name == name) {
+                Some(group) => group,
+                None => continue,
+            };
+
+            if let Err(err) = browser.switch_tab(tab).await {
+                tracing::warn!(group = %group.name, error = %err, "tab switch failed, restarting");
+                browser.restart().await?;
+            }
+
+            control.pass_started(name, iteration);
+            let pass = Pass { iteration, cancel: &tokens[tab], journal, artifacts: &artifacts, notifiers: &notifiers, control };
+            let result = process_group(group, &mut browser, &pass).await;
+            control.pass_ended(name, &result);
+
+            // decide whether to continue looping over groups
+            match result {
+                Ok(_) => {
+                    tracing::info!(group = %group.name, iteration, outcome = "ok", "group ended OK");
+                    notify_success(&current, &notifiers, &group.name, iteration).await;
+                    finish(control, &names);
+                    browser.close().await?;
+                    return Ok(());
+                },
+                // a signal, or a stop of only this group
+                Err(BrowserOutcome::Cancelled) => {
+                    if cancel.is_cancelled() {
+                        break 'passes;
+                    }
+                },
+                Err(err) => {
+                    if needs_restart(&err) {
+                        tracing::warn!(group = %group.name, iteration, error = %err, "unexpected error, restarting");
+                        restarts += 1;
+                        control.restarted(name);
+                        notify_restarts(&current, &notifiers, &group.name, restarts, &err).await;
+                        browser.restart().await?;
+                    } else {
+                        restarts = 0;
+                    }
+                    // otherwise silently continue looping, expected error
+                },
+            };
+        }
+    }
+
+    tracing::info!(iteration, "stopped after {} iteration(s)", iteration);
+    finish(control, &names);
+    browser.close().await?;
+    Ok(())
+}
+
+// every group gets its own task and browser session, with its own driver and
+// marionette port. A restart only affects the group that needed it.
+// The group that ends OK cancels the shared token, which interrupts the
+// others wherever they are waiting
+pub async fn run_concurrent(config: Arc<Config>, groups: &[usize], iterations: Option<u64>, cancel: &CancelToken, journal: &Journal, control: &Control) -> Result<(), Box<dyn Error>> {
+    let stop_all = cancel.child();
+
+    let mut tasks = Vec::new();
+    for (slot,index) in groups.iter().enumerate() {
+        let config = config.clone();
+        let stop_all = stop_all.clone();
+        let journal = journal.clone();
+        let control = control.clone();
+        let index = *index;
+        tasks.push(tokio::spawn(async move {
+            let result = run_group(&config, index, slot, iterations, &stop_all, &journal, &control).await;
+            control.finished(&config.groups[index].name);
+            if let Err(err) = &result {
+                tracing::error!(group = %config.groups[index].name, error = %err, "group stopped");
+            }
+            result
+        }));
+    }
+
+    let mut failed = 0;
+    for task in tasks {
+        match task.await {
+            Ok(Ok(_)) => {},
+            Ok(Err(_)) => failed += 1,
+            Err(err) => {
+                tracing::error!(error = %err, "group task panicked");
+                failed += 1;
+            },
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{} group(s) stopped with an error", failed).into());
+    }
+    Ok(())
+}
+
+// errors are Strings so the task stays Send
+async fn run_group(config: &Config, index: usize, slot: usize, iterations: Option<u64>, stop_all: &CancelToken, journal: &Journal, control: &Control) -> Result<(), String> {
+    let name = &config.groups[index].name;
+    let mut browser = Browser::new(
+        1,Duration::from_millis(config.timeout),
+        config.group_driver_settings(&config.groups[index], slot)?,
+        &config.screenshot_path,
+    ).await.map_err(|err| err.to_string())?;
+
+    // stopping the group through the control API cancels only this one
+    let cancel = control.register(name, stop_all);
+    let mut restarts = 0;
+    let mut iteration = 0;
+    while !cancel.is_cancelled() && iterations.is_none_or(|max| iteration < max) {
+        match control.next_pass(name) {
+            Next::Run => {},
+            Next::Wait => {
+                if cancel.sleep(CONTROL_POLL).await.is_err() {
+                    break;
+                }
+                continue;
+            },
+            Next::Stop => break,
+        }
+        // the session keeps the browser settings it was started with
+        let current = control.config();
+        let group = match current.groups.iter().find(|group| &group.name == name) {
+            Some(group) => group,
+            None => break,
+        };
+        iteration += 1;
+
+        let artifacts = Artifacts::new(&current);
+        let notifiers = control.notifiers();
+        control.pass_started(name, iteration);
+        let pass = Pass { iteration, cancel: &cancel, journal, artifacts: &artifacts, notifiers: &notifiers, control };
+        let result = process_group(group, &mut browser, &pass).await;
+        control.pass_ended(name, &result);
+
+        let restart = match result {
+            Ok(_) => {
+                tracing::info!(group = %group.name, iteration, outcome = "ok", "group ended OK, stopping all groups");
+                stop_all.cancel();
+                notify_success(&current, &notifiers, &group.name, iteration).await;
+                break;
+            },
+            Err(BrowserOutcome::Cancelled) => {
+                break;
+            },
+            Err(err) => {
+                if needs_restart(&err) {
+                    tracing::warn!(group = %group.name, iteration, error = %err, "unexpected error, restarting");
+                    restarts += 1;
+                    control.restarted(name);
+                    notify_restarts(&current, &notifiers, &group.name, restarts, &err).await;
+                    true
+                } else {
+                    restarts = 0;
+                    false
+                }
+            },
+        };
+        if restart {
+            browser.restart().await.map_err(|err| err.to_string())?;
+        }
+    }
+
+    if !stop_all.is_cancelled() {
+        tracing::info!(group = %name, iteration, "group stopped after {} iteration(s)", iteration);
+    }
+    browser.close().await.map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+fn finish(control: &Control, names: &[String]) {
+    for name in names {
+        control.finished(name);
+    }
+}
+
+async fn notify_success(config: &Config, notifiers: &Notifiers, group: &str, iteration: u64) {
+    let message = format!("[{}] ended OK after {} pass(es)", group, iteration);
+    notifiers.send_all(&config.notify.success, &Notification::new("success", group, message)).await;
+}
+
+// once per streak of restarts, when it reaches notify.restart_count
+async fn notify_restarts(config: &Config, notifiers: &Notifiers, group: &str, restarts: u32, err: &BrowserOutcome) {
+    if restarts != config.notify.restart_count {
+        return;
+    }
+    let message = format!("[{}] restarted the browser {} times in a row, last error: {}", group, restarts, err);
+    notifiers.send_all(&config.notify.restarts, &Notification::new("restarts", group, message)).await;
+}
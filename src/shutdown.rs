@@ -0,0 +1,72 @@
+use std::io::{self, Write};
+
+use crate::cancel::CancelToken;
+use crate::process;
+
+// exit status after a signal stopped the bot and everything was cleaned up
+pub const EXIT_INTERRUPTED: i32 = 130;
+// exit status when a second signal forced the bot to exit immediately
+pub const EXIT_FORCED: i32 = 137;
+
+// SIGINT and SIGTERM on unix, Ctrl-C elsewhere
+struct Signals {
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+}
+
+impl Signals {
+    #[cfg(unix)]
+    fn new() -> io::Result<Signals> {
+        use tokio::signal::unix::{signal, SignalKind};
+        Ok(Signals {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn new() -> io::Result<Signals> {
+        Ok(Signals {})
+    }
+
+    // name of the next signal received
+    #[cfg(unix)]
+    async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = self.interrupt.recv() => "SIGINT",
+            _ = self.terminate.recv() => "SIGTERM",
+        }
+    }
+
+    #[cfg(not(unix))]
+    async fn recv(&mut self) -> &'static str {
+        tokio::signal::ctrl_c().await.ok();
+        "Ctrl-C"
+    }
+}
+
+// the first signal cancels the token so the running groups stop, close their
+// sessions and kill their drivers. A second one kills the drivers and exits
+// right away
+pub fn handle_signals(cancel: CancelToken) -> io::Result<()> {
+    let mut signals = Signals::new()?;
+    tokio::spawn(async move {
+        let signal = signals.recv().await;
+        tracing::warn!(signal, "{} received, stopping. Send it again to exit immediately", signal);
+        cancel.cancel();
+
+        let signal = signals.recv().await;
+        tracing::warn!(signal, "{} received again, exiting", signal);
+        process::kill_all();
+        flush();
+        std::process::exit(EXIT_FORCED);
+    });
+    Ok(())
+}
+
+pub fn flush() {
+    io::stdout().flush().ok();
+    io::stderr().flush().ok();
+}
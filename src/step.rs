@@ -1,51 +1,166 @@
 use crate::config::*;
+use crate::assert::*;
+use crate::backend::BrowserBackend;
 use crate::browser::*;
-use std::time::{Instant};
-use std::{thread, time};
+use crate::cancel::CancelToken;
+use crate::journal::{add_retry, Retry};
+use crate::notify::{Notification, Notifiers};
+use crate::vars::Variables;
+use crate::wait::wait_for;
+use std::time::{Duration, Instant};
+
+// state shared by the steps of one pass of a group
+pub struct StepContext<'a> {
+    // name of the group, for notifications
+    pub group: &'a str,
+    // every wait ends early when this is cancelled
+    pub cancel: &'a CancelToken,
+    // where Notify steps send to
+    pub notifiers: &'a Notifiers,
+    // filled by Extract, read through ${name} in step fields
+    pub vars: Variables,
+    // the group's on_dialog
+    pub on_dialog: Option<DialogAction>,
+    // how often the current step's action has been tried, from 1
+    pub attempt: u32,
+    // errors of the current step that were retried under wait_max
+    pub retries: Vec<Retry>,
+    // files written by the current step
+    pub artifacts: Vec<String>,
+}
+
+// a step that keeps opening dialogs is not retried forever
+const MAX_DIALOGS_PER_STEP: usize = 5;
 
 // process a single step in sites.toml
 // handle waiting/retry here
 // return actual result
-pub async fn process_step(step: &Step, browser: &mut Browser) -> Result<(), BrowserOutcome> {
+pub async fn process_step(step: &Step, browser: &mut dyn BrowserBackend, context: &mut StepContext<'_>) -> Result<(), BrowserOutcome> {
     let start_time = Instant::now();
+    let mut dialogs = 0;
+    context.attempt = 0;
+    context.retries.clear();
+    context.artifacts.clear();
 
     loop {
+        context.attempt += 1;
         if step.delay > 0 {
-            thread::sleep(time::Duration::from_millis(step.delay));
+            context.cancel.sleep(Duration::from_millis(step.delay)).await?;
+        } else if context.cancel.is_cancelled() {
+            return Err(BrowserOutcome::Cancelled);
         }
-        match process_action(step, browser).await {
+        match process_action(step, browser, context).await {
             Ok(_) => { return Ok(()) },
+            Err(BrowserOutcome::UnhandledAlert(text)) => {
+                match context.on_dialog {
+                    Some(action) if dialogs < MAX_DIALOGS_PER_STEP => {
+                        dialogs += 1;
+                        tracing::warn!(step = %step.name, attempt = context.attempt, dialog = %text, "answering dialog with {:?}", action);
+                        browser.answer_dialog(action, "").await?;
+                        add_retry(&mut context.retries, BrowserOutcome::UnhandledAlert(text).to_string());
+                    },
+                    // dismissed so the following steps and passes are not blocked by it
+                    _ => {
+                        tracing::warn!(step = %step.name, attempt = context.attempt, dialog = %text, "dismissing unexpected dialog");
+                        browser.answer_dialog(DialogAction::Dismiss, "").await.ok();
+                        return Err(BrowserOutcome::UnhandledAlert(text));
+                    },
+                }
+            },
             Err(err) => { 
                 if start_time.elapsed().as_millis() as u64 >= step.wait_max {
                     return Result::Err(err);
                 }
+                tracing::trace!(step = %step.name, attempt = context.attempt, error = %err, "retrying step");
+                add_retry(&mut context.retries, err.to_string());
             },
         }
     }
 }
 
 // process a step's action in sites.toml
-pub async fn process_action(step: &Step, browser: &mut Browser) -> Result<(), BrowserOutcome> {
+pub async fn process_action(step: &Step, browser: &mut dyn BrowserBackend, context: &mut StepContext<'_>) -> Result<(), BrowserOutcome> {
 
     match &step.action {
         StepAction::Navigate{url, anti_cache} => {
-            let mut final_url = url.clone();
+            let mut final_url = context.vars.interpolate(url)?;
             if *anti_cache {
                 final_url = format!("{}?{}",final_url,rand::random::<u64>());
             }
             browser.goto(&final_url).await?
         },
         StepAction::Wait(time) => {
-            thread::sleep(time::Duration::from_millis(*time));
+            context.cancel.sleep(Duration::from_millis(*time)).await?;
         },
-        StepAction::Screenshot => {
-            browser.screenshot().await?;
+        StepAction::Screenshot{full_page, selector, name} => {
+            let selector = match selector {
+                Some(selector) => Some(locate(context, selector)?),
+                None => None,
+            };
+            let name = if name.is_empty() { &step.name } else { name };
+            let path = browser.screenshot(*full_page, selector.as_ref(), name).await?;
+            context.artifacts.push(path);
         },
-        StepAction::MatchUrl(url) => {
-            if browser.current_url().await?.contains(url) {
-                return Ok(());
-            } else {
-                return Err(BrowserOutcome::MatchUrlFail(url.clone()));
+        StepAction::MatchUrl(url_match) => {
+            match_url(url_match, browser, context).await?;
+        },
+        StepAction::WaitFor{condition, timeout, poll_interval} => {
+            wait_for(condition, Duration::from_millis(*timeout), Duration::from_millis(*poll_interval), browser, context).await?;
+        },
+        StepAction::AssertPrice{selector, max, currency} => {
+            let selector = locate(context, selector)?;
+            let text = browser.find_text(&selector).await?;
+            let price = crate::price::parse_price(&text, currency)
+                .map_err(BrowserOutcome::PriceUnreadable)?;
+            if price > *max {
+                return Err(BrowserOutcome::PriceTooHigh(price, *max));
+            }
+        },
+        StepAction::MatchTitle{value, mode} => {
+            match_title(value, *mode, browser, context).await?;
+        },
+        StepAction::MatchText{selector, value, mode} => {
+            let selector = locate(context, selector)?;
+            let value = context.vars.interpolate(value)?;
+            let text = browser.find_text(&selector).await?;
+            if !text_matches(*mode, &value, &text)? {
+                return Err(failed(&format!("MatchText {}", selector), describe_match(*mode, &value), format!("\"{}\"", text.trim())));
+            }
+        },
+        StepAction::MatchAttribute{selector, attr, value, mode} => {
+            let selector = locate(context, selector)?;
+            let value = context.vars.interpolate(value)?;
+            let check = format!("MatchAttribute {} {}", selector, attr);
+            match browser.find_attribute(&selector, attr).await? {
+                Some(actual) => {
+                    if !text_matches(*mode, &value, &actual)? {
+                        return Err(failed(&check, describe_match(*mode, &value), format!("\"{}\"", actual)));
+                    }
+                },
+                None => {
+                    return Err(failed(&check, describe_match(*mode, &value), "no attribute".to_string()));
+                },
+            }
+        },
+        StepAction::AssertDisplayed{selector, displayed} => {
+            let selector = locate(context, selector)?;
+            let actual = browser.is_displayed(&selector).await?;
+            if actual != *displayed {
+                return Err(failed(&format!("AssertDisplayed {}", selector), shown(*displayed), shown(actual)));
+            }
+        },
+        StepAction::AssertEnabled{selector, enabled} => {
+            let selector = locate(context, selector)?;
+            let actual = browser.is_enabled(&selector).await?;
+            if actual != *enabled {
+                return Err(failed(&format!("AssertEnabled {}", selector), usable(*enabled), usable(actual)));
+            }
+        },
+        StepAction::AssertCount{selector, op, count} => {
+            let selector = locate(context, selector)?;
+            let actual = browser.count(&selector).await?;
+            if !count_matches(*op, actual, *count) {
+                return Err(failed(&format!("AssertCount {}", selector), describe_count(*op, *count), actual.to_string()));
             }
         },
         StepAction::Refresh => {
@@ -54,29 +169,143 @@ pub async fn process_action(step: &Step, browser: &mut Browser) -> Result<(), Br
         StepAction::End => {
             return Err(BrowserOutcome::EarlyEnd);
         },
+        StepAction::Script{code, args, into, is_async, ..} => {
+            let mut values = Vec::new();
+            for arg in args {
+                values.push(serde_json::Value::String(context.vars.interpolate(arg)?));
+            }
+            let result = browser.run_script(code, values, *is_async).await?;
+            if into.is_empty() {
+                if !truthy(&result) {
+                    return Err(failed("Script", "a truthy result".to_string(), result.to_string()));
+                }
+            } else {
+                let value = match result {
+                    serde_json::Value::String(value) => value,
+                    serde_json::Value::Null => String::new(),
+                    other => other.to_string(),
+                };
+                context.vars.set(into, value);
+            }
+        },
+        StepAction::Dialog{action, text, expect_text} => {
+            let text = context.vars.interpolate(text)?;
+            let expect_text = context.vars.interpolate(expect_text)?;
+            let shown = match browser.dialog_text().await? {
+                Some(shown) => shown,
+                None => return Err(failed("Dialog", "an open dialog".to_string(), "none".to_string())),
+            };
+            if !shown.contains(&expect_text) {
+                return Err(failed("Dialog", describe_match(MatchMode::Contains, &expect_text), format!("\"{}\"", shown)));
+            }
+            browser.answer_dialog(*action, &text).await?;
+        },
+        StepAction::Notify{message, channel} => {
+            let notification = Notification::new("step", context.group, context.vars.interpolate(message)?);
+            context.notifiers.send(channel, &notification).await
+                .map_err(BrowserOutcome::NotifyFailed)?;
+        },
         StepAction::TopWindow => {
             browser.top_window().await?;
         },
+        StepAction::ParentFrame => {
+            browser.parent_frame().await?;
+        },
+        StepAction::SwitchFrame(target) => {
+            match target {
+                FrameTarget::Index(index) => browser.switch_frame_index(*index).await?,
+                FrameTarget::Name(name) => browser.switch_frame(&crate::locator::frame_named(&context.vars.interpolate(name)?)).await?,
+                FrameTarget::Selector(selector) => browser.switch_frame(&locate(context, selector)?).await?,
+            }
+        },
         StepAction::Find{selector, action} => {
+            let selector = &locate(context, selector)?;
             match action {
                 FindAction::Click => {
                     browser.click(selector).await?
                 },
                 FindAction::Insert(value) => {
-                    browser.insert(selector, value).await?
+                    let value = context.vars.interpolate(value)?;
+                    browser.insert(selector, &value).await?
                 },
                 FindAction::None => {
                     browser.find(selector).await?;
                 },
                 FindAction::SwitchFrame => {
-                    let element = browser.find(selector).await?;
-                    browser.switch_frame(element).await?;
-                }
+                    browser.switch_frame(selector).await?;
+                },
+                FindAction::Clear => {
+                    browser.clear(selector).await?;
+                },
+                FindAction::Type{text, delay_per_key} => {
+                    let text = context.vars.interpolate(text)?;
+                    if *delay_per_key == 0 {
+                        browser.send_keys(selector, &text).await?;
+                    } else {
+                        for (index, key) in text.chars().enumerate() {
+                            if index > 0 {
+                                context.cancel.sleep(Duration::from_millis(*delay_per_key)).await?;
+                            }
+                            browser.send_keys(selector, &key.to_string()).await?;
+                        }
+                    }
+                },
+                FindAction::SendKeys(keys) => {
+                    let keys = crate::keys::translate(&context.vars.interpolate(keys)?)
+                        .map_err(BrowserOutcome::InvalidKeys)?;
+                    browser.send_keys(selector, &keys).await?;
+                },
+                FindAction::Select(option) => {
+                    let option = match option {
+                        SelectBy::Value(value) => SelectBy::Value(context.vars.interpolate(value)?),
+                        SelectBy::Text(text) => SelectBy::Text(context.vars.interpolate(text)?),
+                        SelectBy::Index(index) => SelectBy::Index(*index),
+                    };
+                    browser.select(selector, &option).await?;
+                },
+                FindAction::Check => {
+                    browser.set_checked(selector, true).await?;
+                },
+                FindAction::Uncheck => {
+                    browser.set_checked(selector, false).await?;
+                },
+                FindAction::Hover => {
+                    browser.hover(selector).await?;
+                },
+                FindAction::ScrollIntoView => {
+                    browser.scroll_into_view(selector).await?;
+                },
+                FindAction::DoubleClick => {
+                    browser.double_click(selector).await?;
+                },
+                FindAction::RightClick => {
+                    browser.right_click(selector).await?;
+                },
+                FindAction::Extract{into, attr} => {
+                    let value = if attr == "text" {
+                        browser.find_text(selector).await?
+                    } else {
+                        match browser.find_attribute(selector, attr).await? {
+                            Some(value) => value,
+                            None => {
+                                return Err(BrowserOutcome::MissingAttribute(format!("{} on {}", attr, selector)));
+                            },
+                        }
+                    };
+                    context.vars.set(into, value);
+                },
             }
         },
         StepAction::Special(action) => {
             match action {
                 SpecialAction::SolveAmazonReCaptcha => {
+                    // the solver below needs pyo3, until it is enabled again an
+                    // open captcha fails the step instead of being passed over
+                    let form = Selector { by: By::Css, value: "form[action='/errors/validateCaptcha']".to_string(), index: 0 };
+                    if browser.count(&form).await? > 0 {
+                        return Err(BrowserOutcome::ReCaptchaIssue("the captcha solver is disabled in this build".to_string()));
+                    }
+
                     // let image_selector = "form[action='/errors/validateCaptcha'] img".to_string();
                     // let image_attr = "src".to_string();
 
@@ -110,4 +339,71 @@ pub async fn process_action(step: &Step, browser: &mut Browser) -> Result<(), Br
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+// compare the current url with a MatchUrl pattern. On a regex match the
+// named groups become variables for the following steps
+pub async fn match_url(url_match: &UrlMatch, browser: &mut dyn BrowserBackend, context: &mut StepContext<'_>) -> Result<(), BrowserOutcome> {
+    let pattern = context.vars.interpolate(url_match.pattern())?;
+    let (mode, component) = (url_match.mode(), url_match.component());
+    let url = browser.current_url().await?;
+    let actual = url_component(&url, component)?;
+    let check = match component {
+        UrlComponent::Full => "MatchUrl",
+        UrlComponent::Host => "MatchUrl host",
+        UrlComponent::Path => "MatchUrl path",
+        UrlComponent::Query => "MatchUrl query",
+    };
+
+    if mode == MatchMode::Regex {
+        let regex = compile(&pattern)?;
+        match regex.captures(&actual) {
+            Some(captures) => {
+                for name in regex.capture_names().flatten() {
+                    if let Some(value) = captures.name(name) {
+                        context.vars.set(name, value.as_str().to_string());
+                    }
+                }
+            },
+            None => {
+                return Err(failed(check, describe_match(mode, &pattern), format!("\"{}\"", actual)));
+            },
+        }
+    } else if !text_matches(mode, &pattern, &actual)? {
+        return Err(failed(check, describe_match(mode, &pattern), format!("\"{}\"", actual)));
+    }
+    Ok(())
+}
+
+pub async fn match_title(value: &str, mode: MatchMode, browser: &mut dyn BrowserBackend, context: &mut StepContext<'_>) -> Result<(), BrowserOutcome> {
+    let value = context.vars.interpolate(value)?;
+    let title = browser.title().await?;
+    if !text_matches(mode, &value, &title)? {
+        return Err(failed("MatchTitle", describe_match(mode, &value), format!("\"{}\"", title)));
+    }
+    Ok(())
+}
+
+// JavaScript truthiness of a script result
+fn truthy(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Null => false,
+        serde_json::Value::Bool(value) => *value,
+        serde_json::Value::Number(number) => number.as_f64().is_none_or(|number| number != 0.0 && !number.is_nan()),
+        serde_json::Value::String(value) => !value.is_empty(),
+        serde_json::Value::Array(_) | serde_json::Value::Object(_) => true,
+    }
+}
+
+// the selector with ${name} replaced in its value
+pub fn locate(context: &StepContext<'_>, selector: &Selector) -> Result<Selector, BrowserOutcome> {
+    Ok(Selector { value: context.vars.interpolate(&selector.value)?, ..selector.clone() })
+}
+
+pub fn shown(displayed: bool) -> String {
+    if displayed { "displayed".to_string() } else { "hidden".to_string() }
+}
+
+pub fn usable(enabled: bool) -> String {
+    if enabled { "enabled".to_string() } else { "disabled".to_string() }
+}
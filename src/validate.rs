@@ -0,0 +1,640 @@
+use serde_derive::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use toml::Spanned;
+
+use crate::condition::Condition;
+use crate::config::*;
+use crate::keys;
+use crate::vars;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// one problem found in a config file, located by line and column
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub location: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}:{}:{}: {}: {} {}", self.path, self.line, self.column, severity, self.location, self.message)
+    }
+}
+
+// the parts of sites.toml diagnostics point at, deserialized a second time
+// with their byte spans. Unknown fields are ignored, Config already checked them
+#[derive(Deserialize)]
+struct SpannedConfig {
+    #[serde(default)]
+    groups: Vec<SpannedGroup>,
+    #[serde(default)]
+    notifiers: HashMap<String, Spanned<toml::Value>>,
+    notify: Option<Spanned<toml::Value>>,
+    control: Option<Spanned<toml::Value>>,
+}
+
+#[derive(Deserialize)]
+struct SpannedGroup {
+    name: Spanned<String>,
+    #[serde(default)]
+    steps: Vec<SpannedStep>,
+}
+
+#[derive(Deserialize)]
+struct SpannedStep {
+    name: Option<Spanned<String>>,
+    if_cond: Option<Spanned<String>>,
+    if_not_cond: Option<Spanned<String>>,
+    when: Option<Spanned<String>>,
+    action: Spanned<toml::Value>,
+}
+
+// collects diagnostics and turns byte offsets into line/column
+struct Checker<'a> {
+    path: &'a str,
+    source: &'a str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, severity: Severity, offset: usize, location: &str, message: String) {
+        let (line, column) = line_column(self.source, offset);
+        self.diagnostics.push(Diagnostic {
+            severity,
+            path: self.path.to_string(),
+            line,
+            column,
+            location: location.to_string(),
+            message,
+        });
+    }
+}
+
+// load a config and report everything wrong with it. Only unreadable or
+// malformed TOML is an Err, everything else becomes a Diagnostic.
+// Config::resolve prepares the steps, the checks only read them.
+// groups is the --group selection, it decides the slots of concurrent groups
+pub fn check_config(path: &str, groups: &[String]) -> Result<(Config, Vec<Diagnostic>), Box<dyn Error>> {
+    let source = fs::read_to_string(path)?;
+    check_source(path, &source, groups)
+}
+
+// check_config on a config that is already read, path is only for the diagnostics
+// and for finding Script files
+pub fn check_source(path: &str, source: &str, groups: &[String]) -> Result<(Config, Vec<Diagnostic>), Box<dyn Error>> {
+    let mut config: Config = toml::from_str(source)
+        .map_err(|err| format!("{}: {}", path, err))?;
+    config.resolve(path);
+    let spanned: SpannedConfig = toml::from_str(source)
+        .map_err(|err| format!("{}: {}", path, err))?;
+
+    let mut checker = Checker { path, source, diagnostics: Vec::new() };
+
+    let mut group_names: HashMap<String, usize> = HashMap::new();
+    for (group, spans) in config.groups.iter().zip(spanned.groups.iter()) {
+        let location = format!("[{}]", group.name);
+        if let Some(first) = group_names.insert(group.name.clone(), spans.name.start()) {
+            let (line, _) = line_column(source, first);
+            checker.report(Severity::Error, spans.name.start(), &location,
+                format!("duplicate group name, first used on line {}", line));
+        }
+        check_group(&mut checker, group, spans, &config.notifiers);
+    }
+
+    for (name, settings) in &config.notifiers {
+        let offset = spanned.notifiers.get(name).map(|span| span.start()).unwrap_or(0);
+        check_notifier(&mut checker, settings, offset, &format!("[notifiers.{}]", name));
+    }
+    if let Some(control) = &config.control {
+        let offset = spanned.control.as_ref().map(|span| span.start()).unwrap_or(0);
+        match control.address.parse::<std::net::SocketAddr>() {
+            Ok(address) if !address.ip().is_loopback() => checker.report(Severity::Error, offset, "[control]",
+                format!("address {} is not a localhost address", control.address)),
+            Ok(_) => {},
+            Err(err) => checker.report(Severity::Error, offset, "[control]",
+                format!("invalid address \"{}\": {}", control.address, err)),
+        }
+    }
+
+    let notify_offset = spanned.notify.as_ref().map(|span| span.start()).unwrap_or(0);
+    for channel in config.notify.success.iter().chain(config.notify.restarts.iter()) {
+        if !config.notifiers.contains_key(channel) {
+            checker.report(Severity::Error, notify_offset, "[notify]", format!("no notifier named {}", channel));
+        }
+    }
+
+    if config.concurrent {
+        check_sessions(&mut checker, &config, &spanned, groups);
+    }
+
+    Ok((config, checker.diagnostics))
+}
+
+// every concurrent group gets its own browser session, numbered by its position
+// in the selection the same way run_concurrent does. A browser profile can only
+// be open in one session at a time, and no two sessions can listen on one port
+fn check_sessions(checker: &mut Checker, config: &Config, spanned: &SpannedConfig, groups: &[String]) {
+    // a name the config no longer has is a group a reload removed, main
+    // rejects unknown --group names before anything runs
+    let known: Vec<String> = groups.iter()
+        .filter(|name| config.groups.iter().any(|group| &group.name == *name))
+        .cloned()
+        .collect();
+    if !groups.is_empty() && known.is_empty() {
+        return;
+    }
+    let selection = config.select_groups(&known).unwrap_or_default();
+
+    let mut profiles: HashMap<String, String> = HashMap::new();
+    let mut ports: HashMap<u64, String> = HashMap::new();
+    for (slot, index) in selection.iter().enumerate() {
+        let group = &config.groups[*index];
+        let offset = spanned.groups[*index].name.start();
+        let location = format!("[{}]", group.name);
+        let settings = match config.group_driver_settings(group, slot) {
+            Ok(settings) => settings,
+            Err(err) => {
+                checker.report(Severity::Error, offset, &location, err);
+                continue;
+            },
+        };
+
+        let profile = format!("{}|{}", settings.profile, settings.profile_directory);
+        if let Some(other) = profiles.insert(profile, group.name.clone()) {
+            checker.report(Severity::Error, offset, &location,
+                format!("uses the same browser profile as [{}], concurrent groups need their own profile", other));
+        }
+
+        let mut used = vec![("driver port", settings.driver_port as u64)];
+        if settings.browser == BrowserKind::Firefox {
+            used.push(("marionette port", settings.marionette_port));
+        }
+        for (kind, port) in used {
+            let owner = format!("the {} of [{}]", kind, group.name);
+            if let Some(other) = ports.insert(port, owner) {
+                checker.report(Severity::Error, offset, &location,
+                    format!("{} {} is already {}", kind, port, other));
+            }
+        }
+    }
+}
+
+fn check_group(checker: &mut Checker, group: &Group, spans: &SpannedGroup, notifiers: &HashMap<String, NotifierSettings>) {
+    // position of every named step, for duplicate and forward reference checks
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for (index, (step, step_spans)) in group.steps.iter().zip(spans.steps.iter()).enumerate() {
+        if step.name.is_empty() {
+            continue;
+        }
+        let location = format!("[{}:{}]", group.name, step.name);
+        match positions.get(&step.name) {
+            Some(first) => {
+                let offset = step_spans.name.as_ref().map(|name| name.start()).unwrap_or(step_spans.action.start());
+                checker.report(Severity::Error, offset, &location,
+                    format!("duplicate step name, first used by step {} of the group", first + 1));
+            },
+            None => {
+                positions.insert(step.name.clone(), index);
+            },
+        }
+    }
+
+    // variables set by the steps before the current one
+    let mut defined: HashSet<String> = HashSet::new();
+    let mut unreachable_after: Option<String> = None;
+    for (index, (step, step_spans)) in group.steps.iter().zip(spans.steps.iter()).enumerate() {
+        let location = format!("[{}:{}]", group.name, step.name);
+
+        if let Some(end) = &unreachable_after {
+            checker.report(Severity::Warning, step_spans.action.start(), &location,
+                format!("can never run, [{}] always ends the group before it", end));
+        }
+
+        if step.logging.is_some() {
+            checker.report(Severity::Warning, step_spans.action.start(), &location,
+                "logging is replaced by log, use log = \"debug\" instead of logging = false".to_string());
+        }
+
+        check_condition(checker, step, step_spans, &location);
+
+        if let Some(condition) = &step.condition {
+            let offset = step_spans.when.as_ref()
+                .or(step_spans.if_cond.as_ref())
+                .or(step_spans.if_not_cond.as_ref())
+                .map(|span| span.start())
+                .unwrap_or(step_spans.action.start());
+            for name in condition.names() {
+                match positions.get(name) {
+                    None => checker.report(Severity::Error, offset, &location,
+                        format!("condition refers to unknown step [{}]", name)),
+                    Some(position) if *position >= index => checker.report(Severity::Error, offset, &location,
+                        format!("condition refers to [{}], which has not run yet when this step is reached", name)),
+                    _ => {},
+                }
+            }
+        }
+
+        check_script(checker, &step.action, step_spans.action.start(), &location);
+        check_action(checker, &step.action, step_spans.action.start(), &location);
+
+        if let StepAction::Notify{channel, ..} = &step.action {
+            if channel.is_empty() && notifiers.is_empty() {
+                checker.report(Severity::Error, step_spans.action.start(), &location, "Notify without any [notifiers] configured".to_string());
+            } else if !channel.is_empty() && !notifiers.contains_key(channel) {
+                checker.report(Severity::Error, step_spans.action.start(), &location, format!("no notifier named {}", channel));
+            }
+        }
+
+        for template in templates(&step.action) {
+            for name in vars::references(template) {
+                if !defined.contains(name) {
+                    checker.report(Severity::Error, step_spans.action.start(), &location,
+                        format!("${{{}}} is not set by any earlier step", name));
+                }
+            }
+        }
+        defined.extend(produced_variables(&step.action));
+
+        // every step after the first unconditional End is reported against it
+        if let StepAction::End = step.action {
+            if step.condition.is_none() && !step.optional && unreachable_after.is_none() {
+                unreachable_after = Some(step.name.clone());
+            }
+        }
+    }
+}
+
+// when, or the legacy if_cond/if_not_cond pair
+fn check_condition(checker: &mut Checker, step: &Step, spans: &SpannedStep, location: &str) {
+    let when = match &spans.when {
+        Some(when) if !step.when.is_empty() => when,
+        _ => return,
+    };
+
+    if !step.if_cond.is_empty() || !step.if_not_cond.is_empty() {
+        checker.report(Severity::Error, when.start(), location,
+            "use either when or if_cond/if_not_cond, not both".to_string());
+    }
+
+    if let Err(err) = Condition::parse(&step.when) {
+        // +1 skips the opening quote of the TOML string
+        checker.report(Severity::Error, when.start() + 1 + err.offset, location,
+            format!("invalid when \"{}\": {}", step.when, err.message));
+    }
+}
+
+fn check_script(checker: &mut Checker, action: &StepAction, offset: usize, location: &str) {
+    if let StepAction::Script{source, file, read_error, ..} = action {
+        match (source.is_empty(), file.is_empty()) {
+            (true, true) => checker.report(Severity::Error, offset, location, "Script needs a source or a file".to_string()),
+            (false, false) => checker.report(Severity::Error, offset, location, "use either source or file, not both".to_string()),
+            (true, false) => {
+                if let Some(err) = read_error {
+                    checker.report(Severity::Error, offset, location, err.clone());
+                }
+            },
+            (false, true) => {},
+        }
+    }
+}
+
+fn check_action(checker: &mut Checker, action: &StepAction, offset: usize, location: &str) {
+    match action {
+        StepAction::Navigate{url, ..} => {
+            if let Err(err) = url::Url::parse(&vars::placeholder(url, "x")) {
+                checker.report(Severity::Error, offset, location, format!("invalid url \"{}\": {}", url, err));
+            }
+        },
+        StepAction::Find{selector, action} => {
+            if selector.value.trim().is_empty() {
+                checker.report(Severity::Error, offset, location, "empty selector".to_string());
+            }
+            if let FindAction::SendKeys(keys) = action {
+                if let Err(err) = keys::translate(&vars::placeholder(keys, "x")) {
+                    checker.report(Severity::Error, offset, location, format!("invalid SendKeys: {}", err));
+                }
+            }
+        },
+        StepAction::MatchTitle{value, mode} => check_pattern(checker, *mode, value, offset, location),
+        StepAction::MatchText{selector, value, mode}
+        | StepAction::MatchAttribute{selector, value, mode, ..} => {
+            if selector.value.trim().is_empty() {
+                checker.report(Severity::Error, offset, location, "empty selector".to_string());
+            }
+            check_pattern(checker, *mode, value, offset, location);
+        },
+        StepAction::AssertDisplayed{selector, ..}
+        | StepAction::AssertEnabled{selector, ..}
+        | StepAction::AssertCount{selector, ..} if selector.value.trim().is_empty() => {
+            checker.report(Severity::Error, offset, location, "empty selector".to_string());
+        },
+        StepAction::WaitFor{condition, poll_interval, ..} => {
+            match condition {
+                WaitCondition::Present(selector)
+                | WaitCondition::Visible(selector)
+                | WaitCondition::Clickable(selector)
+                | WaitCondition::Gone(selector) => {
+                    if selector.value.trim().is_empty() {
+                        checker.report(Severity::Error, offset, location, "empty selector".to_string());
+                    }
+                },
+                WaitCondition::Url(url_match) => check_pattern(checker, url_match.mode(), url_match.pattern(), offset, location),
+                WaitCondition::Title{value, mode} => check_pattern(checker, *mode, value, offset, location),
+                WaitCondition::DocumentReady => {},
+            }
+            if *poll_interval == 0 {
+                checker.report(Severity::Error, offset, location, "poll_interval must be above 0".to_string());
+            }
+        },
+        StepAction::SwitchFrame(FrameTarget::Name(value))
+        | StepAction::SwitchFrame(FrameTarget::Selector(Selector{value, ..})) if value.trim().is_empty() => {
+            checker.report(Severity::Error, offset, location, "empty frame name or selector".to_string());
+        },
+        StepAction::AssertPrice{selector, max, ..} => {
+            if selector.value.trim().is_empty() {
+                checker.report(Severity::Error, offset, location, "empty selector".to_string());
+            }
+            if *max <= 0.0 {
+                checker.report(Severity::Error, offset, location, format!("AssertPrice max must be above 0, got {}", max));
+            }
+        },
+        StepAction::MatchUrl(url_match) => {
+            if url_match.pattern().is_empty() && url_match.mode() == MatchMode::Contains {
+                checker.report(Severity::Warning, offset, location, "empty MatchUrl always matches".to_string());
+            }
+            check_pattern(checker, url_match.mode(), url_match.pattern(), offset, location);
+        },
+        _ => {},
+    }
+}
+
+fn check_notifier(checker: &mut Checker, settings: &NotifierSettings, offset: usize, location: &str) {
+    match settings {
+        NotifierSettings::Webhook{url, ..} => {
+            if let Err(err) = url::Url::parse(url) {
+                checker.report(Severity::Error, offset, location, format!("invalid url \"{}\": {}", url, err));
+            }
+        },
+        NotifierSettings::Smtp{server, tls, username, from, to, ..} => {
+            if server.trim().is_empty() || from.trim().is_empty() {
+                checker.report(Severity::Error, offset, location, "smtp needs a server and a from address".to_string());
+            }
+            if to.is_empty() {
+                checker.report(Severity::Error, offset, location, "smtp needs at least one to address".to_string());
+            }
+            // they end up in SMTP commands and headers, where these would end the line or the address
+            for address in std::iter::once(from).chain(to.iter()) {
+                if address.contains(['\r', '\n', '<', '>']) {
+                    checker.report(Severity::Error, offset, location, format!("invalid address {:?}, it cannot contain line breaks, < or >", address));
+                }
+            }
+            if *tls == SmtpTls::None && !username.is_empty() {
+                checker.report(Severity::Warning, offset, location, "the password is sent unencrypted with tls = \"none\"".to_string());
+            }
+        },
+        NotifierSettings::Command{program, ..} => {
+            if program.trim().is_empty() {
+                checker.report(Severity::Error, offset, location, "empty program".to_string());
+            }
+        },
+    }
+}
+
+// regexes are checked with placeholder values, the real ones only exist at run time
+fn check_pattern(checker: &mut Checker, mode: MatchMode, value: &str, offset: usize, location: &str) {
+    if mode == MatchMode::Regex {
+        if let Err(err) = regex::Regex::new(&vars::placeholder(value, "x")) {
+            checker.report(Severity::Error, offset, location, format!("invalid regex \"{}\": {}", value, err));
+        }
+    }
+}
+
+// step fields that ${name} is replaced in
+fn templates(action: &StepAction) -> Vec<&str> {
+    match action {
+        StepAction::Navigate{url, ..} => vec![url],
+        StepAction::MatchUrl(url_match) => vec![url_match.pattern()],
+        StepAction::Screenshot{selector, ..} => selector.iter().map(|selector| selector.value.as_str()).collect(),
+        StepAction::AssertPrice{selector, ..} => vec![&selector.value],
+        StepAction::MatchTitle{value, ..} => vec![value],
+        StepAction::MatchText{selector, value, ..}
+        | StepAction::MatchAttribute{selector, value, ..} => vec![&selector.value, value],
+        StepAction::AssertDisplayed{selector, ..}
+        | StepAction::AssertEnabled{selector, ..}
+        | StepAction::AssertCount{selector, ..} => vec![&selector.value],
+        StepAction::Script{args, ..} => args.iter().map(|arg| arg.as_str()).collect(),
+        StepAction::Dialog{text, expect_text, ..} => vec![text, expect_text],
+        StepAction::Notify{message, ..} => vec![message],
+        StepAction::SwitchFrame(FrameTarget::Name(name)) => vec![name],
+        StepAction::SwitchFrame(FrameTarget::Selector(selector)) => vec![&selector.value],
+        StepAction::WaitFor{condition, ..} => match condition {
+            WaitCondition::Present(selector)
+            | WaitCondition::Visible(selector)
+            | WaitCondition::Clickable(selector)
+            | WaitCondition::Gone(selector) => vec![&selector.value],
+            WaitCondition::Url(url_match) => vec![url_match.pattern()],
+            WaitCondition::Title{value, ..} => vec![value],
+            WaitCondition::DocumentReady => vec![],
+        },
+        StepAction::Find{selector, action} => match action {
+            FindAction::Insert(value)
+            | FindAction::Type{text: value, ..}
+            | FindAction::SendKeys(value)
+            | FindAction::Select(SelectBy::Value(value))
+            | FindAction::Select(SelectBy::Text(value)) => vec![&selector.value, value],
+            _ => vec![&selector.value],
+        },
+        _ => vec![],
+    }
+}
+
+// variables a step sets when it succeeds
+fn produced_variables(action: &StepAction) -> Vec<String> {
+    match action {
+        StepAction::Find{action: FindAction::Extract{into, ..}, ..} => vec![into.clone()],
+        StepAction::Script{into, ..} if !into.is_empty() => vec![into.clone()],
+        StepAction::MatchUrl(url_match)
+        | StepAction::WaitFor{condition: WaitCondition::Url(url_match), ..} if url_match.mode() == MatchMode::Regex => {
+            match regex::Regex::new(&vars::placeholder(url_match.pattern(), "x")) {
+                Ok(regex) => regex.capture_names().flatten().map(|name| name.to_string()).collect(),
+                Err(_) => vec![],
+            }
+        },
+        _ => vec![],
+    }
+}
+
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a config around the given groups table entries
+    fn check(groups: &str) -> Vec<Diagnostic> {
+        let source = format!("profile = \"p\"\nscreenshot_path = \"\"\ntimeout = 1000\ngroups = [\n{}\n]\n", groups);
+        check_source("sites.toml", &source, &[]).unwrap().1
+    }
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<String> {
+        diagnostics.iter().map(|diagnostic| format!("{} {}", diagnostic.location, diagnostic.message)).collect()
+    }
+
+    #[test]
+    fn every_step_after_an_unconditional_end_is_unreachable() {
+        let diagnostics = check(r#"
+            { name = "a", steps = [
+                { name = "start", action = "Refresh" },
+                { name = "stop", action = "End" },
+                { name = "one", action = "Refresh" },
+                { name = "two", action = "Refresh" },
+                { name = "stop_again", action = "End" },
+            ]},
+        "#);
+        assert_eq!(messages(&diagnostics), vec![
+            "[a:one] can never run, [stop] always ends the group before it",
+            "[a:two] can never run, [stop] always ends the group before it",
+            "[a:stop_again] can never run, [stop] always ends the group before it",
+        ]);
+        assert!(diagnostics.iter().all(|diagnostic| diagnostic.severity == Severity::Warning));
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (9, 42));
+    }
+
+    #[test]
+    fn conditional_or_optional_end_leaves_the_rest_reachable() {
+        let diagnostics = check(r#"
+            { name = "a", steps = [
+                { name = "buy", action = "Refresh", optional = true },
+                { action = "End", if_not_cond = "buy" },
+                { action = "End", optional = true },
+                { name = "after", action = "Refresh" },
+            ]},
+        "#);
+        assert!(diagnostics.is_empty(), "{:?}", messages(&diagnostics));
+    }
+
+    fn concurrent(groups: &[String]) -> Vec<String> {
+        let source = r#"
+            profile = "p"
+            screenshot_path = ""
+            timeout = 1000
+            concurrent = true
+            driver_port = 4444
+            marionette_port = 2828
+            groups = [
+                { name = "a", profile = "a", steps = [] },
+                { name = "b", profile = "b", steps = [] },
+                { name = "c", profile = "c", driver_port = 4445, steps = [] },
+            ]
+        "#;
+        messages(&check_source("sites.toml", source, groups).unwrap().1)
+    }
+
+    #[test]
+    fn derived_ports_clash_with_explicit_ones() {
+        // b gets slot 1, driver port 4445, which c sets explicitly
+        assert_eq!(concurrent(&[]), vec!["[c] driver port 4445 is already the driver port of [b]"]);
+    }
+
+    #[test]
+    fn slots_follow_the_group_selection() {
+        // without a, b is slot 0 and keeps the global port 4444
+        assert!(concurrent(&["b".to_string(), "c".to_string()]).is_empty());
+        assert_eq!(concurrent(&["c".to_string(), "a".to_string(), "b".to_string()]),
+            vec!["[c] driver port 4445 is already the driver port of [b]"]);
+    }
+
+    #[test]
+    fn derived_ports_past_65535_are_reported() {
+        let source = r#"
+            profile = "p"
+            screenshot_path = ""
+            timeout = 1000
+            concurrent = true
+            driver_port = 65535
+            groups = [
+                { name = "a", profile = "a", steps = [] },
+                { name = "b", profile = "b", steps = [] },
+            ]
+        "#;
+        let diagnostics = check_source("sites.toml", source, &[]).unwrap().1;
+        assert_eq!(messages(&diagnostics), vec!["[b] driver port 65535 + slot 1 is past port 65535"]);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn concurrent_groups_need_their_own_profile() {
+        let source = r#"
+            profile = "shared"
+            screenshot_path = ""
+            timeout = 1000
+            concurrent = true
+            groups = [
+                { name = "a", steps = [] },
+                { name = "b", steps = [] },
+            ]
+        "#;
+        let diagnostics = check_source("sites.toml", source, &[]).unwrap().1;
+        assert_eq!(messages(&diagnostics),
+            vec!["[b] uses the same browser profile as [a], concurrent groups need their own profile"]);
+        assert!(check_source("sites.toml", source, &["b".to_string()]).unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn smtp_addresses_cannot_break_lines() {
+        let source = r#"
+            profile = "p"
+            screenshot_path = ""
+            timeout = 1000
+            groups = []
+
+            [notifiers.mail]
+            type = "smtp"
+            server = "smtp.example.com"
+            from = "bot@example.com>\r\nRCPT TO:<spam@example.com"
+            to = ["me@example.com", "<you@example.com>", "ok@example.com"]
+        "#;
+        let diagnostics = check_source("sites.toml", source, &[]).unwrap().1;
+        assert_eq!(messages(&diagnostics), vec![
+            "[notifiers.mail] invalid address \"bot@example.com>\\r\\nRCPT TO:<spam@example.com\", it cannot contain line breaks, < or >",
+            "[notifiers.mail] invalid address \"<you@example.com>\", it cannot contain line breaks, < or >",
+        ]);
+        assert!(diagnostics.iter().all(|diagnostic| diagnostic.severity == Severity::Error));
+    }
+
+    #[test]
+    fn script_problems_are_reported_without_touching_the_config() {
+        let diagnostics = check(r#"
+            { name = "a", steps = [
+                { name = "none", action = { Script = {} } },
+                { name = "both", action = { Script = { source = "return 1;", file = "x.js" } } },
+                { name = "missing", action = { Script = { file = "no-such-script.js" } } },
+            ]},
+        "#);
+        let messages = messages(&diagnostics);
+        assert_eq!(messages.len(), 3, "{:?}", messages);
+        assert_eq!(messages[0], "[a:none] Script needs a source or a file");
+        assert_eq!(messages[1], "[a:both] use either source or file, not both");
+        assert!(messages[2].starts_with("[a:missing] cannot read no-such-script.js: "), "{}", messages[2]);
+    }
+}
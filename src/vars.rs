@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use crate::browser::BrowserOutcome;
+
+// values captured from pages during one pass of a group, referenced as
+// ${name} in step fields. $${ is a literal ${
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    values: HashMap<String, String>,
+}
+
+impl Variables {
+    pub fn new() -> Variables {
+        Variables::default()
+    }
+
+    pub fn set(&mut self, name: &str, value: String) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    // replace every ${name} in template, a name without a value fails the step
+    pub fn interpolate(&self, template: &str) -> Result<String, BrowserOutcome> {
+        let mut result = String::with_capacity(template.len());
+        for part in parse(template) {
+            match part {
+                Part::Text(text) => result.push_str(text),
+                Part::Variable(name) => match self.values.get(name) {
+                    Some(value) => result.push_str(value),
+                    None => return Err(BrowserOutcome::UnknownVariable(name.to_string())),
+                },
+            }
+        }
+        Ok(result)
+    }
+}
+
+// names of the variables a template refers to, used by config validation
+pub fn references(template: &str) -> Vec<&str> {
+    parse(template).into_iter()
+        .filter_map(|part| match part {
+            Part::Variable(name) => Some(name),
+            Part::Text(_) => None,
+        })
+        .collect()
+}
+
+// the template with every variable replaced by a fixed value, so the rest
+// of it can be validated before the real values are known
+pub fn placeholder(template: &str, value: &str) -> String {
+    parse(template).into_iter()
+        .map(|part| match part {
+            Part::Text(text) => text,
+            Part::Variable(_) => value,
+        })
+        .collect()
+}
+
+enum Part<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+}
+
+// an unterminated ${ is kept as text
+fn parse(template: &str) -> Vec<Part<'_>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("${") {
+        // $${ escapes the variable syntax
+        if start > 0 && rest[..start].ends_with('$') {
+            parts.push(Part::Text(&rest[..start - 1]));
+            parts.push(Part::Text("${"));
+            rest = &rest[start + 2..];
+            continue;
+        }
+        let end = match rest[start + 2..].find('}') {
+            Some(end) => start + 2 + end,
+            None => break,
+        };
+        parts.push(Part::Text(&rest[..start]));
+        parts.push(Part::Variable(rest[start + 2..end].trim()));
+        rest = &rest[end + 1..];
+    }
+
+    parts.push(Part::Text(rest));
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> Variables {
+        let mut vars = Variables::new();
+        vars.set("price", "499.99".to_string());
+        vars.set("sku", "B08164VTWH".to_string());
+        vars
+    }
+
+    #[test]
+    fn interpolates_every_variable() {
+        assert_eq!(vars().interpolate("/dp/${sku}?p=${ price }&again=${sku}").unwrap(), "/dp/B08164VTWH?p=499.99&again=B08164VTWH");
+        assert_eq!(vars().interpolate("no variables").unwrap(), "no variables");
+        assert_eq!(vars().interpolate("").unwrap(), "");
+    }
+
+    #[test]
+    fn later_values_replace_earlier_ones() {
+        let mut vars = vars();
+        vars.set("price", "1".to_string());
+        assert_eq!(vars.interpolate("${price}").unwrap(), "1");
+    }
+
+    #[test]
+    fn unknown_variable_fails() {
+        match vars().interpolate("${price} ${missing}") {
+            Err(BrowserOutcome::UnknownVariable(name)) => assert_eq!(name, "missing"),
+            other => panic!("expected UnknownVariable, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn escaped_and_unterminated_variables_are_text() {
+        assert_eq!(vars().interpolate("$${sku} is ${sku}").unwrap(), "${sku} is B08164VTWH");
+        assert_eq!(vars().interpolate("cost: $$${price}").unwrap(), "cost: $${price}");
+        assert_eq!(vars().interpolate("${sku").unwrap(), "${sku");
+        assert_eq!(vars().interpolate("$ {sku} $").unwrap(), "$ {sku} $");
+    }
+
+    #[test]
+    fn references_and_placeholders_skip_escapes() {
+        assert_eq!(references("${a} $${b} ${ c }"), vec!["a", "c"]);
+        assert_eq!(placeholder("${a}/$${b}/${c}", "x"), "x/${b}/x");
+    }
+}
@@ -0,0 +1,190 @@
+use std::time::{Duration, Instant};
+
+use crate::assert::failed;
+use crate::backend::BrowserBackend;
+use crate::browser::BrowserOutcome;
+use crate::config::*;
+use crate::step::*;
+
+// check the condition every poll_interval until it holds. A missing element
+// or a failed match means not yet, any other error ends the wait
+pub async fn wait_for(condition: &WaitCondition, timeout: Duration, poll_interval: Duration,
+    browser: &mut dyn BrowserBackend, context: &mut StepContext<'_>) -> Result<(), BrowserOutcome> {
+
+    let start_time = Instant::now();
+    loop {
+        let last = match check(condition, browser, context).await {
+            Ok(_) => return Ok(()),
+            Err(err @ BrowserOutcome::NoSuchElement(_))
+            | Err(err @ BrowserOutcome::AssertionFailed{..}) => err,
+            Err(err) => return Err(err),
+        };
+
+        let elapsed = start_time.elapsed();
+        if elapsed >= timeout {
+            return Err(BrowserOutcome::WaitTimedOut(format!("{} after {}ms, {}", describe(condition), timeout.as_millis(), last)));
+        }
+        context.cancel.sleep(poll_interval.min(timeout - elapsed)).await?;
+    }
+}
+
+async fn check(condition: &WaitCondition, browser: &mut dyn BrowserBackend, context: &mut StepContext<'_>) -> Result<(), BrowserOutcome> {
+    match condition {
+        WaitCondition::Present(selector) => {
+            browser.find(&locate(context, selector)?).await
+        },
+        WaitCondition::Visible(selector) => {
+            let selector = locate(context, selector)?;
+            if !browser.is_displayed(&selector).await? {
+                return Err(failed(&format!("Visible {}", selector), shown(true), shown(false)));
+            }
+            Ok(())
+        },
+        WaitCondition::Clickable(selector) => {
+            let selector = locate(context, selector)?;
+            if !browser.is_displayed(&selector).await? {
+                return Err(failed(&format!("Clickable {}", selector), shown(true), shown(false)));
+            }
+            if !browser.is_enabled(&selector).await? {
+                return Err(failed(&format!("Clickable {}", selector), usable(true), usable(false)));
+            }
+            Ok(())
+        },
+        WaitCondition::Gone(selector) => {
+            let selector = locate(context, selector)?;
+            match browser.is_displayed(&selector).await {
+                Ok(true) => Err(failed(&format!("Gone {}", selector), "missing or hidden".to_string(), shown(true))),
+                Ok(false) | Err(BrowserOutcome::NoSuchElement(_)) => Ok(()),
+                Err(err) => Err(err),
+            }
+        },
+        WaitCondition::Url(url_match) => match_url(url_match, browser, context).await,
+        WaitCondition::Title{value, mode} => match_title(value, *mode, browser, context).await,
+        WaitCondition::DocumentReady => {
+            let state = browser.ready_state().await?;
+            if state != "complete" {
+                return Err(failed("DocumentReady", "\"complete\"".to_string(), format!("\"{}\"", state)));
+            }
+            Ok(())
+        },
+    }
+}
+
+fn describe(condition: &WaitCondition) -> String {
+    match condition {
+        WaitCondition::Present(selector) => format!("{} not present", selector),
+        WaitCondition::Visible(selector) => format!("{} not visible", selector),
+        WaitCondition::Clickable(selector) => format!("{} not clickable", selector),
+        WaitCondition::Gone(selector) => format!("{} still displayed", selector),
+        WaitCondition::Url(url_match) => format!("url does not match {}", url_match.pattern()),
+        WaitCondition::Title{value, ..} => format!("title does not match {}", value),
+        WaitCondition::DocumentReady => "document not ready".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::CancelToken;
+    use crate::mock::{MockBrowser, MockElement, MockPage};
+    use crate::notify::Notifiers;
+    use crate::vars::Variables;
+
+    const SHOP: &str = "https://shop.test/item";
+
+    fn css(value: &str) -> Selector {
+        Selector { by: By::Css, value: value.to_string(), index: 0 }
+    }
+
+    async fn browser() -> MockBrowser {
+        let mut browser = MockBrowser::new(1).page(SHOP, MockPage::new()
+            .element("#buy", MockElement::new())
+            .element("#spinner", MockElement::new().hidden()));
+        browser.goto(SHOP).await.unwrap();
+        browser
+    }
+
+    async fn wait(condition: WaitCondition, timeout: u64, poll_interval: u64, browser: &mut MockBrowser, cancel: &CancelToken) -> Result<(), BrowserOutcome> {
+        let notifiers = Notifiers::dry_run();
+        let mut context = StepContext {
+            group: "test",
+            cancel,
+            notifiers: &notifiers,
+            vars: Variables::new(),
+            on_dialog: None,
+            attempt: 1,
+            retries: Vec::new(),
+            artifacts: Vec::new(),
+        };
+        wait_for(&condition, Duration::from_millis(timeout), Duration::from_millis(poll_interval), browser, &mut context).await
+    }
+
+    #[tokio::test]
+    async fn times_out_after_polling() {
+        let mut browser = browser().await;
+        let start = Instant::now();
+        match wait(WaitCondition::Present(css("#missing")), 50, 10, &mut browser, &CancelToken::new()).await {
+            Err(BrowserOutcome::WaitTimedOut(message)) => {
+                assert!(message.starts_with("#missing not present after 50ms, "), "{}", message);
+            },
+            other => panic!("expected WaitTimedOut, got {:?}", other),
+        }
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(browser.history.iter().filter(|entry| entry.as_str() == "find #missing").count() > 2);
+    }
+
+    #[tokio::test]
+    async fn present_element_ends_the_wait_at_once() {
+        let mut browser = browser().await;
+        wait(WaitCondition::Present(css("#buy")), 1000, 10, &mut browser, &CancelToken::new()).await.unwrap();
+        assert_eq!(browser.history.iter().filter(|entry| entry.as_str() == "find #buy").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn gone_accepts_hidden_and_missing_elements() {
+        let mut browser = browser().await;
+        let cancel = CancelToken::new();
+        wait(WaitCondition::Gone(css("#spinner")), 50, 10, &mut browser, &cancel).await.unwrap();
+        wait(WaitCondition::Gone(css("#missing")), 50, 10, &mut browser, &cancel).await.unwrap();
+        match wait(WaitCondition::Gone(css("#buy")), 50, 10, &mut browser, &cancel).await {
+            Err(BrowserOutcome::WaitTimedOut(message)) => {
+                assert!(message.starts_with("#buy still displayed after 50ms, "), "{}", message);
+            },
+            other => panic!("expected WaitTimedOut, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn other_errors_end_the_wait() {
+        let mut browser = browser().await;
+        let condition = WaitCondition::Url(UrlMatch::Pattern{
+            pattern: "(".to_string(),
+            mode: MatchMode::Regex,
+            component: UrlComponent::Full,
+        });
+        let start = Instant::now();
+        match wait(condition, 10_000, 10, &mut browser, &CancelToken::new()).await {
+            Err(BrowserOutcome::InvalidPattern(_)) => {},
+            other => panic!("expected InvalidPattern, got {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn cancel_interrupts_the_poll_sleep() {
+        let mut browser = browser().await;
+        let cancel = CancelToken::new();
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::delay_for(Duration::from_millis(20)).await;
+            canceller.cancel();
+        });
+        let start = Instant::now();
+        match wait(WaitCondition::Present(css("#missing")), 60_000, 10_000, &mut browser, &cancel).await {
+            Err(BrowserOutcome::Cancelled) => {},
+            other => panic!("expected Cancelled, got {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(browser.history.iter().filter(|entry| entry.as_str() == "find #missing").count(), 1);
+    }
+}